@@ -1,49 +1,408 @@
 #![no_std]
 
-use bincode::encode_to_vec;
-
 mod file;
 pub use file::*;
+mod flash;
+pub use flash::*;
+mod format;
+pub use format::*;
 
 extern crate alloc;
 
-use anyhow::Result;
+use alloc::{collections::VecDeque, format, string::String, vec, vec::Vec};
+use anyhow::{anyhow, Result};
 use hashbrown::HashMap;
 use log::*;
-use msg::{DsCmd, DsHk, DsOutData, DsTlmSet, Instance, Msg, TlmSetId};
-use rfe::*;
+use msg::{
+    CmdError, CmdResult, DsCmd, DsFileStatus, DsFlushPolicy, DsHk, DsOutData, DsTlmSet, Event,
+    EventSeverity, Instance, Msg, MsgKind, MsgPacket, TargetMsg, TlmSetId,
+};
+pub use rfe::msg::DsFileSettings;
+use rfe::{time::Timestamp, *};
+
+/// How often the tlm set paths are checked against the quotas, microseconds
+pub const QUOTA_CHECK_PERIOD: Timestamp = 10_000_000;
 
 #[derive(Debug, Default)]
 pub struct DsData<F: DsFile> {
     hk: DsHk,
     out_data: DsOutData,
-    file_list: HashMap<TlmSetId, F>,
+    file_list: HashMap<TlmSetId, DsFileState<F>>,
     enabled: bool,
+    last_quota_check: Option<Timestamp>,
+}
+
+/// Disk limits shared by all tlm sets, 0 is unlimited
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DsQuota {
+    /// bytes of all tlm set paths together, the oldest files are deleted past it
+    pub max_total_size: u64,
+    /// low priority tlm sets are suspended while a disk has less free bytes
+    pub min_free_space: u64,
+}
+
+/// File of a tlm set and what was written to it since it was opened
+#[derive(Debug, Default)]
+pub struct DsFileState<F: DsFile> {
+    file: F,
+    path: String,
+    status: DsFileStatus,
+    /// met time of the first write to the file
+    opened: Option<Timestamp>,
+    /// of the next record in the file
+    seq: u32,
+    /// met time of the last flush or when the file was opened
+    last_flush: Timestamp,
+}
+
+impl<F: DsFile> DsFileState<F> {
+    pub fn new(id: TlmSetId, path: String) -> Self {
+        Self {
+            file: F::new(path.clone()),
+            path,
+            status: DsFileStatus {
+                id,
+                ..Default::default()
+            },
+            opened: None,
+            seq: 0,
+            last_flush: 0,
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.file.close();
+        self.opened = None;
+        self.seq = 0;
+        self.status.name.clear();
+        self.status.bytes = 0;
+        self.status.pending_bytes = 0;
+    }
+
+    /// The next write opens a new file
+    pub fn rotate(&mut self) {
+        info!(
+            "rotating file {} of tlm set {} after {} bytes",
+            self.status.name, self.status.id, self.status.bytes
+        );
+        self.close();
+        self.status.rotations += 1;
+    }
+
+    pub fn is_too_old(&self, settings: &DsFileSettings, met_time: Timestamp) -> bool {
+        settings.enabled
+            && settings.max_age > 0
+            && self
+                .opened
+                .is_some_and(|x| met_time - x >= settings.max_age as Timestamp * 1_000_000)
+    }
+
+    /// Writes packet as the next record, new files start with a header.
+    /// Rotates first if the record would take the file over its max size,
+    /// records are never split between files
+    pub fn write(
+        &mut self,
+        packet: &MsgPacket,
+        settings: &DsFileSettings,
+        rfe: &Rfe,
+    ) -> Result<usize> {
+        let mut record = encode_record(self.seq, packet);
+        if settings.enabled
+            && settings.max_size > 0
+            && self.status.bytes > 0
+            && self.status.bytes + record.len() as u32 > settings.max_size
+        {
+            self.rotate();
+            record = encode_record(self.seq, packet);
+        }
+
+        let mut written = 0;
+        if self.opened.is_none() {
+            let header = DsFileHeader::new(rfe.get_instance(), rfe.get_system_time());
+            written = self.file.write(&header.encode())?;
+            if written == 0 {
+                self.status.open_errors += 1;
+                return Err(anyhow!(
+                    "failed to open a file for tlm set {}, record dropped",
+                    self.status.id
+                ));
+            }
+            self.opened = Some(rfe.get_met_time());
+            self.last_flush = rfe.get_met_time();
+            self.status.name = self.file.get_name();
+            self.status.bytes += written as u32;
+        }
+        let record_written = self.file.write(&record)?;
+        self.seq += 1;
+        self.status.bytes += record_written as u32;
+        self.status.pending_bytes += (written + record_written) as u32;
+        Ok(written + record_written)
+    }
+
+    pub fn is_flush_due(&self, policy: &DsFlushPolicy, met_time: Timestamp) -> bool {
+        self.status.pending_bytes > 0
+            && match policy {
+                DsFlushPolicy::Buffered => false,
+                DsFlushPolicy::Bytes(bytes) => self.status.pending_bytes >= *bytes,
+                DsFlushPolicy::Seconds(seconds) => {
+                    met_time - self.last_flush >= *seconds as Timestamp * 1_000_000
+                }
+                DsFlushPolicy::EveryPacket => true,
+            }
+    }
+
+    /// Errors are logged
+    pub fn flush_if_due(&mut self, policy: &DsFlushPolicy, rfe: &Rfe) {
+        if !self.is_flush_due(policy, rfe.get_met_time()) {
+            return;
+        }
+        if let Err(e) = self.flush(rfe) {
+            error!(
+                "failed to flush ds file for tlm set {}: {e}",
+                self.status.id
+            );
+        }
+    }
+
+    pub fn flush(&mut self, rfe: &Rfe) -> Result<()> {
+        if self.status.pending_bytes == 0 {
+            return Ok(());
+        }
+        let start = rfe.get_met_time();
+        self.file.flush()?;
+        self.last_flush = rfe.get_met_time();
+        self.status.flush_time = (self.last_flush - start) as u32;
+        self.status.max_flush_time = self.status.max_flush_time.max(self.status.flush_time);
+        self.status.pending_bytes = 0;
+        Ok(())
+    }
+
+    /// Returns true if the file was deleted
+    pub fn delete(&mut self, file: &DsFileInfo, reason: &str) -> bool {
+        match self.file.delete_file(&file.name) {
+            Ok(()) => {
+                info!(
+                    "deleted {} of tlm set {} with {} bytes, {reason}",
+                    file.name, self.status.id, file.size
+                );
+                self.status.dir_bytes = self.status.dir_bytes.saturating_sub(file.size);
+                self.status.deleted_files += 1;
+                true
+            }
+            Err(e) => {
+                error!("failed to delete {}: {e}", file.name);
+                false
+            }
+        }
+    }
+
+    pub fn get_status(&self) -> &DsFileStatus {
+        &self.status
+    }
+}
+
+/// Tlm sets recording to the same path, their files are counted and deleted together
+#[derive(Debug, Default)]
+struct DsPathUsage {
+    path: String,
+    /// sorted, deletions are counted on the first set
+    ids: Vec<TlmSetId>,
+    /// strictest quota of the sets, 0 is unlimited
+    quota: u64,
+    dir_bytes: u64,
+    /// oldest first, files open by any of the sets are left out
+    deletable: VecDeque<DsFileInfo>,
+    deleted_files: u32,
+    deleted_bytes: u64,
+}
+
+impl DsPathUsage {
+    /// Returns false if only open files are left
+    fn delete_oldest<F: DsFile>(
+        &mut self,
+        file_list: &mut HashMap<TlmSetId, DsFileState<F>>,
+        reason: &str,
+    ) -> bool {
+        let Some(oldest) = self.deletable.pop_front() else {
+            return false;
+        };
+        let file = file_list.get_mut(&self.ids[0]).unwrap();
+        if file.delete(&oldest, reason) {
+            self.dir_bytes = self.dir_bytes.saturating_sub(oldest.size);
+            self.deleted_files += 1;
+            self.deleted_bytes += oldest.size;
+        }
+        true
+    }
 }
 
-pub struct DsFileSettings {
-    pub max_size: u32,
-    pub max_age: u32,
-    pub enabled: bool,
+fn send_event(rfe: &mut Rfe, severity: EventSeverity, text: String) {
+    let instance = rfe.get_instance();
+    rfe.send(Msg::Event(Event {
+        severity,
+        source: "ds".into(),
+        instance,
+        text,
+        throttled: 0,
+    }));
 }
 
 pub struct Ds<F: DsFile> {
     data: DsData<F>,
     tlm_sets: HashMap<TlmSetId, DsTlmSet>,
     start_enabled: bool,
+    quota: DsQuota,
 }
 
 impl<F: DsFile> Ds<F> {
     pub fn new(tlm_sets: HashMap<TlmSetId, DsTlmSet>, start_enabled: bool) -> Self {
+        Self::new_with_quota(tlm_sets, start_enabled, DsQuota::default())
+    }
+
+    pub fn new_with_quota(
+        tlm_sets: HashMap<TlmSetId, DsTlmSet>,
+        start_enabled: bool,
+        quota: DsQuota,
+    ) -> Self {
         Self {
             data: Default::default(),
             tlm_sets,
             start_enabled,
+            quota,
+        }
+    }
+
+    /// Deletes the oldest files of paths over their quota then over the total quota,
+    /// and suspends low priority sets while the disk is low on space
+    pub fn check_quotas(&mut self, rfe: &mut Rfe) {
+        for tlm_set in self.tlm_sets.values() {
+            self.data
+                .file_list
+                .entry(tlm_set.id)
+                .or_insert_with(|| DsFileState::new(tlm_set.id, tlm_set.path.clone()));
+        }
+
+        let mut ids = self.data.file_list.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        let mut usages: Vec<DsPathUsage> = Vec::new();
+        for id in ids {
+            let path = &self.data.file_list[&id].path;
+            let quota = self.tlm_sets.get(&id).map_or(0, |x| x.quota);
+            match usages.iter_mut().find(|x| &x.path == path) {
+                Some(usage) => {
+                    usage.ids.push(id);
+                    usage.quota = match (usage.quota, quota) {
+                        (0, x) | (x, 0) => x,
+                        (a, b) => a.min(b),
+                    };
+                }
+                None => usages.push(DsPathUsage {
+                    path: path.clone(),
+                    ids: vec![id],
+                    quota,
+                    ..Default::default()
+                }),
+            }
+        }
+
+        for usage in &mut usages {
+            let open = usage
+                .ids
+                .iter()
+                .map(|id| &self.data.file_list[id].status.name)
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>();
+            let file = &self.data.file_list[&usage.ids[0]];
+            usage.dir_bytes = file.status.dir_bytes;
+            match file.file.list_files() {
+                Ok(files) => {
+                    usage.dir_bytes = files.iter().map(|x| x.size).sum();
+                    usage.deletable = files
+                        .into_iter()
+                        .filter(|x| !open.contains(&&x.name))
+                        .collect();
+                }
+                Err(e) => warn!("failed to list files of {}: {e}", usage.path),
+            }
+        }
+
+        for usage in &mut usages {
+            while usage.quota > 0 && usage.dir_bytes > usage.quota {
+                if !usage.delete_oldest(&mut self.data.file_list, "over the tlm set quota") {
+                    warn!("{} is over its quota with only open files left", usage.path);
+                    break;
+                }
+            }
+        }
+
+        let total_bytes = |usages: &[DsPathUsage]| usages.iter().map(|x| x.dir_bytes).sum::<u64>();
+        while self.quota.max_total_size > 0 && total_bytes(&usages) > self.quota.max_total_size {
+            let Some(usage) = usages
+                .iter_mut()
+                .filter(|x| !x.deletable.is_empty())
+                .min_by_key(|x| x.deletable[0].modified)
+            else {
+                warn!("over the total quota with only open files left");
+                break;
+            };
+            usage.delete_oldest(&mut self.data.file_list, "over the total quota");
+        }
+        self.data.out_data.total_bytes = total_bytes(&usages);
+
+        for usage in &usages {
+            for id in &usage.ids {
+                self.data.file_list.get_mut(id).unwrap().status.dir_bytes = usage.dir_bytes;
+            }
+            if usage.deleted_files > 0 {
+                send_event(
+                    rfe,
+                    EventSeverity::Warn,
+                    format!(
+                        "deleted the {} oldest files of {} with {} bytes, over quota",
+                        usage.deleted_files, usage.path, usage.deleted_bytes
+                    ),
+                );
+            }
+        }
+
+        let mut free_space = None;
+        for usage in &usages {
+            match self.data.file_list[&usage.ids[0]].file.get_free_space() {
+                Ok(free) => free_space = Some(free_space.map_or(free, |x: u64| x.min(free))),
+                Err(e) => warn!("failed to get free space of {}: {e}", usage.path),
+            }
+        }
+        let Some(free_space) = free_space else {
+            return;
+        };
+        self.data.out_data.free_space = free_space;
+        let low_disk = self.quota.min_free_space > 0 && free_space < self.quota.min_free_space;
+        if low_disk && !self.data.out_data.low_disk {
+            let text = format!(
+                "{free_space} bytes free, below {}, suspending low priority tlm sets",
+                self.quota.min_free_space
+            );
+            warn!("{text}");
+            send_event(rfe, EventSeverity::Warn, text);
+        } else if !low_disk && self.data.out_data.low_disk {
+            let text = format!("{free_space} bytes free, resuming low priority tlm sets");
+            info!("{text}");
+            send_event(rfe, EventSeverity::Info, text);
+        }
+        self.data.out_data.low_disk = low_disk;
+
+        for (id, file) in &mut self.data.file_list {
+            let suspended = low_disk && self.tlm_sets.get(id).is_some_and(|x| x.low_priority);
+            if suspended && !file.status.suspended {
+                file.close();
+            }
+            file.status.suspended = suspended;
         }
     }
 
     pub fn update_subscriptions(&mut self, rfe: &mut Rfe) {
         rfe.unsubscribe_all();
+        rfe.subscribe(TargetMsg::new(rfe.get_instance(), MsgKind::DsCmd));
         rfe.subscribe_all(
             self.tlm_sets
                 .values()
@@ -53,6 +412,85 @@ impl<F: DsFile> Ds<F> {
                 .map(|x| x.target),
         );
     }
+
+    pub fn handle_cmd(&mut self, rfe: &mut Rfe, cmd: &DsCmd) -> CmdResult {
+        match cmd {
+            DsCmd::Noop => info!("Noop command received"),
+            DsCmd::Reset => {
+                info!("Reset command received");
+                self.data = Default::default();
+                rfe.reset_cmd_counters();
+            }
+            DsCmd::CloseAll => {
+                info!("CloseAll command received");
+                for f in self.data.file_list.values_mut() {
+                    f.close();
+                }
+            }
+            DsCmd::Flush => {
+                info!("Flush command received");
+                let mut result = Ok(());
+                for (id, file) in &mut self.data.file_list {
+                    if let Err(e) = file.flush(rfe) {
+                        error!("failed to flush ds file for tlm set {id}: {e}");
+                        result = Err(CmdError::ExecutionFailed);
+                    }
+                }
+                return result;
+            }
+            DsCmd::Close(f) => {
+                info!("Close command received");
+                if let Some(file) = self.data.file_list.get_mut(f) {
+                    file.close();
+                } else {
+                    error!("Cannot close file {f}, file doesn't exist");
+                    return Err(CmdError::NotFound);
+                }
+            }
+            DsCmd::AddTlmSet(ds_tlm_set) => {
+                info!("received AddTlmSet");
+                if let Err(e) = self.tlm_sets.try_insert(ds_tlm_set.id, ds_tlm_set.clone()) {
+                    error!("Could not add tlm set {} {e}", ds_tlm_set.id);
+                    return Err(CmdError::AlreadyExists);
+                }
+                info!("TlmSet {} added", ds_tlm_set.id);
+                self.update_subscriptions(rfe);
+            }
+            DsCmd::RemoveTlmSet(set_id) => {
+                info!("received RemoveTlmSet");
+                if let Some(_set) = self.tlm_sets.remove(set_id) {
+                    info!("removed tlm set {}", set_id);
+                    self.update_subscriptions(rfe);
+                } else {
+                    warn!("Cannot remove tlm set {}, does not exist", set_id);
+                    return Err(CmdError::NotFound);
+                }
+            }
+            DsCmd::DisableTlmSet(set_id) => {
+                info!("received DisableTlmSet");
+                if let Some(set) = self.tlm_sets.get_mut(set_id) {
+                    info!("set {set_id} is now disabled");
+                    set.enabled = false;
+                    self.update_subscriptions(rfe);
+                } else {
+                    warn!("could not disable set {set_id}, does not exist");
+                    return Err(CmdError::NotFound);
+                }
+            }
+            DsCmd::EnablTlmSet(set_id) => {
+                info!("received EnablTlmSet");
+                if let Some(set) = self.tlm_sets.get_mut(set_id) {
+                    info!("set {set_id} is now enabled");
+                    set.enabled = true;
+                    self.update_subscriptions(rfe);
+                } else {
+                    warn!("could not enable set {set_id}, does not exist");
+                    return Err(CmdError::NotFound);
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<F: DsFile> App for Ds<F> {
@@ -68,74 +506,46 @@ impl<F: DsFile> App for Ds<F> {
         self.data.hk.perf.enter(rfe);
         self.data.out_data.counter += 1;
         self.data.out_data.bytes_written_this_cycle = 0;
+        let met_time = rfe.get_met_time();
+        for (id, file) in &mut self.data.file_list {
+            if let Some(tlm_set) = self.tlm_sets.get(id) {
+                if file.is_too_old(&tlm_set.file_settings, met_time) {
+                    file.rotate();
+                }
+            }
+        }
+        if self
+            .data
+            .last_quota_check
+            .is_none_or(|x| met_time - x >= QUOTA_CHECK_PERIOD)
+        {
+            self.data.last_quota_check = Some(met_time);
+            self.check_quotas(rfe);
+        }
+
+        let mut flush_cmds = Vec::new();
         while let Some(msg) = rfe.recv() {
-            match msg.msg {
-                Msg::DsCmd(cmd) => match cmd {
-                    DsCmd::Noop => info!("Noop command received"),
-                    DsCmd::Reset => {
-                        info!("Reset command received");
-                        self.data = Default::default();
-                    }
-                    DsCmd::CloseAll => {
-                        info!("CloseAll command received");
-                        for f in self.data.file_list.values_mut() {
-                            f.close();
-                        }
-                    }
-                    DsCmd::Close(f) => {
-                        info!("Close command received");
-                        if let Some(file) = self.data.file_list.get_mut(&f) {
-                            file.close();
-                        } else {
-                            error!("Cannot close file {f}, file doesn't exist");
-                        }
-                    }
-                    DsCmd::AddTlmSet(ds_tlm_set) => {
-                        info!("received AddTlmSet");
-                        if let Err(e) = self.tlm_sets.try_insert(ds_tlm_set.id, ds_tlm_set.clone())
-                        {
-                            error!("Could not add tlm set {} {e}", ds_tlm_set.id);
-                        } else {
-                            info!("TlmSet {} added", ds_tlm_set.id);
-                            self.update_subscriptions(rfe);
-                        }
-                    }
-                    DsCmd::RemoveTlmSet(set_id) => {
-                        info!("received RemoveTlmSet");
-                        if let Some(_set) = self.tlm_sets.remove(&set_id) {
-                            info!("removed tlm set {}", set_id);
-                            self.update_subscriptions(rfe);
-                        } else {
-                            warn!("Cannot remove tlm set {}, does not exist", set_id);
-                        }
-                    }
-                    DsCmd::DisableTlmSet(set_id) => {
-                        info!("received DisableTlmSet");
-                        if let Some(set) = self.tlm_sets.get_mut(&set_id) {
-                            info!("set {set_id} is now disabled");
-                            set.enabled = false;
-                            self.update_subscriptions(rfe);
-                        } else {
-                            warn!("could not disable set {set_id}, does not exist");
-                        }
-                    }
-                    DsCmd::EnablTlmSet(set_id) => {
-                        info!("received EnablTlmSet");
-                        if let Some(set) = self.tlm_sets.get_mut(&set_id) {
-                            info!("set {set_id} is now enabled");
-                            set.enabled = true;
-                            self.update_subscriptions(rfe);
-                        } else {
-                            warn!("could not enable set {set_id}, does not exist");
-                        }
-                    }
-                },
+            match &msg.msg {
+                Msg::DsCmd(DsCmd::Flush) => {
+                    // flushed once the records of this cycle are written so they're included
+                    rfe.cmd_accepted(&msg);
+                    flush_cmds.push(msg);
+                }
+                Msg::DsCmd(cmd) => {
+                    let result = self.handle_cmd(rfe, cmd);
+                    rfe.cmd_result(&msg, result);
+                }
                 _ => {
                     if !self.data.enabled {
                         continue;
                     }
 
-                    for tlm_set in self.tlm_sets.values_mut().filter(|x| x.enabled) {
+                    let low_disk = self.data.out_data.low_disk;
+                    for tlm_set in self
+                        .tlm_sets
+                        .values_mut()
+                        .filter(|x| x.enabled && !(low_disk && x.low_priority))
+                    {
                         for item in &mut tlm_set.items {
                             let msg_target = msg.to_target();
                             if msg_target == item.target
@@ -144,22 +554,18 @@ impl<F: DsFile> App for Ds<F> {
                             {
                                 if item.counter % (item.decimation + 1) == 0 {
                                     let file =
-                                        if let Some(f) = self.data.file_list.get_mut(&tlm_set.id) {
-                                            f
-                                        } else {
-                                            let f = F::new(tlm_set.path.clone());
-                                            self.data.file_list.insert(tlm_set.id, f);
-                                            self.data.file_list.get_mut(&tlm_set.id).unwrap()
-                                        };
-
-                                    let bytes = encode_to_vec(&msg, BINCODE_CONFIG)
-                                        .expect("failed serialize ds packet");
-                                    if let Err(e) = file.write(&bytes) {
-                                        error!("file write error: {e}");
-                                    } else {
-                                        self.data.out_data.bytes_written_this_cycle +=
-                                            bytes.len() as u32;
+                                        self.data.file_list.entry(tlm_set.id).or_insert_with(
+                                            || DsFileState::new(tlm_set.id, tlm_set.path.clone()),
+                                        );
+
+                                    match file.write(&msg, &tlm_set.file_settings, rfe) {
+                                        Ok(written) => {
+                                            self.data.out_data.bytes_written_this_cycle +=
+                                                written as u32
+                                        }
+                                        Err(e) => error!("file write error: {e}"),
                                     }
+                                    file.flush_if_due(&tlm_set.flush_policy, rfe);
                                 }
                                 item.counter += 1;
                             }
@@ -169,20 +575,50 @@ impl<F: DsFile> App for Ds<F> {
             }
         }
 
+        for (id, file) in &mut self.data.file_list {
+            if let Some(tlm_set) = self.tlm_sets.get(id) {
+                file.flush_if_due(&tlm_set.flush_policy, rfe);
+            }
+        }
+        for cmd in flush_cmds {
+            let result = self.handle_cmd(rfe, &DsCmd::Flush);
+            rfe.cmd_result(&cmd, result);
+        }
+
         self.data.out_data.bytes_written += self.data.out_data.bytes_written_this_cycle;
+        let mut files = self
+            .data
+            .file_list
+            .values()
+            .map(|x| x.get_status().clone())
+            .collect::<Vec<_>>();
+        files.sort_by_key(|x| x.id);
+        self.data.out_data.files = files;
         self.data.hk.perf.exit(rfe);
     }
 
     fn hk(&mut self, rfe: &mut rfe::Rfe) {
         self.data.hk.counter = self.data.out_data.counter;
+        self.data.hk.cmds = rfe.get_cmd_counters();
         rfe.send(Msg::DsHk(self.data.hk));
     }
 
     fn out_data(&mut self, rfe: &mut rfe::Rfe) {
-        rfe.send(Msg::DsOutData(self.data.out_data));
+        rfe.send(Msg::DsOutData(self.data.out_data.clone()));
     }
 
     fn get_app_rate(&self) -> Rate {
         Rate::Hz1
     }
+
+    fn shutdown(&mut self, rfe: &mut rfe::Rfe) {
+        // records sent during the final cycle are still queued
+        self.run(rfe);
+        for (id, file) in &mut self.data.file_list {
+            if let Err(e) = file.flush(rfe) {
+                error!("failed to flush ds file for tlm set {id}: {e}");
+            }
+            file.close();
+        }
+    }
 }
@@ -1,13 +1,31 @@
 extern crate alloc;
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 use anyhow::Result;
+use rfe::time::Timestamp;
+
+/// A file Ds wrote earlier under a tlm set's path
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DsFileInfo {
+    pub name: String,
+    pub size: u64,
+    /// system time of the last write, orders files from different tlm sets
+    pub modified: Timestamp,
+}
 
 pub trait DsFile: Default {
     fn new(dir: String) -> Self;
     fn close(&mut self);
+    /// Every open must create a new file, opening after close is how Ds rotates files
     fn open(&mut self);
     fn write(&mut self, buf: &[u8]) -> Result<usize>;
     fn flush(&mut self) -> Result<()>;
+    /// Name of the open file, empty when closed
+    fn get_name(&self) -> String;
+    /// Files written by Ds under the path, oldest first
+    fn list_files(&self) -> Result<Vec<DsFileInfo>>;
+    fn delete_file(&mut self, name: &str) -> Result<()>;
+    /// Bytes free on the disk holding the path
+    fn get_free_space(&self) -> Result<u64>;
 }
 
 #[cfg(feature = "std")]
@@ -17,25 +35,43 @@ mod file_std {
     use alloc::{
         format,
         string::{String, ToString},
+        vec::Vec,
     };
-    use anyhow::Result;
+    use anyhow::{anyhow, Result};
     use chrono::Utc;
     use log::*;
-    use std::{fs::File, io::Write, path::Path};
+    use rfe::time::Timestamp;
+    use std::{
+        fs::{read_dir, remove_file, File},
+        io::{BufWriter, Write},
+        path::Path,
+        time::SystemTime,
+    };
+    use sysinfo::Disks;
+
+    use super::{DsFile, DsFileInfo};
 
-    use super::DsFile;
+    /// Bytes buffered before they're written to the file
+    pub const DS_BUFFER_SIZE: usize = 64 * 1024;
 
     #[derive(Debug, Default)]
     pub struct StdDsFile {
         pub dir: String,
-        pub file: Option<File>,
+        pub file: Option<BufWriter<File>>,
         prefix: String,
+        name: String,
+        /// files opened, keeps names unique when files are rotated within a second
+        opened: u32,
     }
 
     impl StdDsFile {
-        fn get_time(prefix: &str) -> String {
+        fn get_file_name(prefix: &str, opened: u32) -> String {
             let date = Utc::now();
-            format!("{}_{}", prefix, date.format("%Y-%m-%d_%H-%M-%S.dat"))
+            format!(
+                "{}_{}_{opened:04}.dat",
+                prefix,
+                date.format("%Y-%m-%d_%H-%M-%S")
+            )
         }
     }
 
@@ -50,18 +86,28 @@ mod file_std {
                     .unwrap_or("unnamed")
                     .to_string(),
                 dir,
+                name: String::new(),
+                opened: 0,
             }
         }
 
         fn close(&mut self) {
+            if let Err(e) = self.flush() {
+                error!("failed to flush {} before closing {e}", self.name);
+            }
             self.file = None;
+            self.name.clear();
         }
 
         fn open(&mut self) {
-            let time = Self::get_time(&self.prefix);
-            let file_path = Path::new(&self.dir).join(time);
+            let name = Self::get_file_name(&self.prefix, self.opened);
+            self.opened += 1;
+            let file_path = Path::new(&self.dir).join(&name);
             self.file = match File::create(&file_path) {
-                Ok(f) => Some(f),
+                Ok(f) => {
+                    self.name = name;
+                    Some(BufWriter::with_capacity(DS_BUFFER_SIZE, f))
+                }
                 Err(e) => {
                     error!("failed to create file at {:?} {}", file_path, e);
                     None
@@ -75,7 +121,8 @@ mod file_std {
             }
 
             if let Some(f) = &mut self.file {
-                return Ok(f.write(buf)?);
+                f.write_all(buf)?;
+                return Ok(buf.len());
             } else {
                 return Ok(0);
             }
@@ -88,6 +135,50 @@ mod file_std {
                 return Ok(());
             }
         }
+
+        fn get_name(&self) -> String {
+            self.name.clone()
+        }
+
+        fn list_files(&self) -> Result<Vec<DsFileInfo>> {
+            let mut files = Vec::new();
+            for ent in read_dir(&self.dir)? {
+                let ent = ent?;
+                let name = ent.file_name().to_string_lossy().to_string();
+                let metadata = ent.metadata()?;
+                if !metadata.is_file() || !name.starts_with(&self.prefix) || !name.ends_with(".dat")
+                {
+                    continue;
+                }
+                let modified = metadata
+                    .modified()?
+                    .duration_since(SystemTime::UNIX_EPOCH)?
+                    .as_micros() as Timestamp;
+                files.push(DsFileInfo {
+                    name,
+                    size: metadata.len(),
+                    modified,
+                });
+            }
+            files.sort_by(|x, y| (x.modified, &x.name).cmp(&(y.modified, &y.name)));
+            Ok(files)
+        }
+
+        fn delete_file(&mut self, name: &str) -> Result<()> {
+            Ok(remove_file(Path::new(&self.dir).join(name))?)
+        }
+
+        fn get_free_space(&self) -> Result<u64> {
+            let dir = Path::new(&self.dir).canonicalize()?;
+            let disks = Disks::new_with_refreshed_list();
+            disks
+                .list()
+                .iter()
+                .filter(|x| dir.starts_with(x.mount_point()))
+                .max_by_key(|x| x.mount_point().as_os_str().len())
+                .map(|x| x.available_space())
+                .ok_or_else(|| anyhow!("no disk found for {}", self.dir))
+        }
     }
 }
 
@@ -0,0 +1,600 @@
+extern crate alloc;
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use anyhow::{anyhow, Result};
+use core::{cmp::Ordering, marker::PhantomData, ops::Range};
+use embedded_storage::nor_flash::{
+    check_erase, check_read, check_write, ErrorType, NorFlash, NorFlashError, NorFlashErrorKind,
+    ReadNorFlash,
+};
+use log::*;
+use rfe::{framing::crc16, time::Timestamp};
+
+use crate::{DsFile, DsFileInfo};
+
+/// "RFDF", starts every sector of a flash log in use
+pub const FLASH_SECTOR_MAGIC: u32 = 0x5246_4446;
+/// Most data bytes in a record, pending bytes are written once there are this many
+pub const FLASH_MAX_RECORD: usize = 1024;
+
+/// magic, seq, crc of magic and seq
+const SECTOR_HEADER_LEN: usize = 10;
+/// data len, file id, crc of the data, crc of the header
+const RECORD_HEADER_LEN: usize = 10;
+
+/// Flash holding the FlashDsFile logs, implemented by the build
+pub trait DsFlashRegions {
+    type Flash: NorFlash;
+
+    /// Calls f with the flash and the byte range of the log for a tlm set path,
+    /// None if the path has no log.
+    /// The range must be aligned to erase sectors and hold at least 2 of them,
+    /// each path needs its own range
+    fn with_region<T>(path: &str, f: impl FnOnce(&mut Self::Flash, Range<u32>) -> T) -> Option<T>;
+}
+
+fn flash_error<E: NorFlashError>(e: E) -> anyhow::Error {
+    anyhow!("flash error {:?}", e.kind())
+}
+
+fn align_up(len: usize, align: usize) -> usize {
+    len.div_ceil(align) * align
+}
+
+/// Sector sequence numbers wrap, they're compared as serial numbers (RFC 1982).
+/// The sectors of a log are always within a few numbers of each other
+fn cmp_seq(a: u32, b: u32) -> Ordering {
+    (a.wrapping_sub(b) as i32).cmp(&0)
+}
+
+fn get_u16(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn get_u32(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RecordInfo {
+    file: u32,
+    /// flash address of the data
+    addr: u32,
+    len: u32,
+}
+
+#[derive(Debug, Default)]
+struct SectorInfo {
+    /// None if the sector is erased or wasn't started by a FlashDsFile
+    seq: Option<u32>,
+    records: Vec<RecordInfo>,
+    /// offset of the first byte after the last good record
+    end: u32,
+    /// a record was cut short or corrupted, the rest of the sector is skipped
+    damaged: bool,
+}
+
+/// Where the next record is written
+#[derive(Debug, Clone, Copy)]
+struct WritePosition {
+    sector: u32,
+    seq: u32,
+    offset: u32,
+}
+
+/// Circular log of records in erase sectors.
+///
+/// A sector starts with a header holding a sequence number, the sector with the highest
+/// one, compared as wrapping serial numbers, is the newest and records are appended to it
+/// until it's full, then the next sector is erased and started. Every sector is erased in
+/// turn, spreading the wear.
+/// Records are written with their header in one write and the header and data crcs
+/// catch records cut short by a power loss, nothing is written after them.
+struct FlashLog<'a, F: NorFlash> {
+    flash: &'a mut F,
+    range: Range<u32>,
+}
+
+impl<'a, F: NorFlash> FlashLog<'a, F> {
+    fn new(flash: &'a mut F, range: Range<u32>) -> Result<Self> {
+        let sector_size = F::ERASE_SIZE as u32;
+        if !range.start.is_multiple_of(sector_size)
+            || !range.end.is_multiple_of(sector_size)
+            || range.end < range.start + 2 * sector_size
+        {
+            return Err(anyhow!(
+                "flash log {range:?} isn't at least 2 sectors of {sector_size} bytes"
+            ));
+        }
+        Ok(Self { flash, range })
+    }
+
+    fn get_align() -> usize {
+        F::WRITE_SIZE.max(F::READ_SIZE)
+    }
+
+    fn get_sector_header_len() -> u32 {
+        align_up(SECTOR_HEADER_LEN, Self::get_align()) as u32
+    }
+
+    fn get_record_header_len() -> u32 {
+        align_up(RECORD_HEADER_LEN, Self::get_align()) as u32
+    }
+
+    /// Data bytes of the largest record
+    fn get_max_record() -> usize {
+        FLASH_MAX_RECORD.min(
+            F::ERASE_SIZE
+                - Self::get_sector_header_len() as usize
+                - Self::get_record_header_len() as usize,
+        )
+    }
+
+    fn get_sector_count(&self) -> u32 {
+        (self.range.end - self.range.start) / F::ERASE_SIZE as u32
+    }
+
+    fn get_sector_addr(&self, sector: u32) -> u32 {
+        self.range.start + sector * F::ERASE_SIZE as u32
+    }
+
+    fn read(&mut self, addr: u32, len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0; align_up(len, F::READ_SIZE)];
+        self.flash.read(addr, &mut buf).map_err(flash_error)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    fn scan_sector(&mut self, sector: u32) -> Result<SectorInfo> {
+        let addr = self.get_sector_addr(sector);
+        let header = self.read(addr, SECTOR_HEADER_LEN)?;
+        if get_u32(&header, 0) != FLASH_SECTOR_MAGIC || get_u16(&header, 8) != crc16(&header[..8]) {
+            return Ok(SectorInfo::default());
+        }
+
+        let mut info = SectorInfo {
+            seq: Some(get_u32(&header, 4)),
+            end: Self::get_sector_header_len(),
+            ..Default::default()
+        };
+        let header_len = Self::get_record_header_len();
+        let sector_size = F::ERASE_SIZE as u32;
+        while info.end + header_len <= sector_size {
+            let header = self.read(addr + info.end, RECORD_HEADER_LEN)?;
+            if header.iter().all(|x| *x == 0xFF) {
+                break;
+            }
+            let len = get_u16(&header, 0) as u32;
+            let total = align_up((header_len + len) as usize, Self::get_align()) as u32;
+            if get_u16(&header, 8) != crc16(&header[..8])
+                || len == 0
+                || info.end + total > sector_size
+            {
+                warn!("damaged record header at {:#x}", addr + info.end);
+                info.damaged = true;
+                break;
+            }
+            let data_addr = addr + info.end + header_len;
+            if get_u16(&header, 6) != crc16(&self.read(data_addr, len as usize)?) {
+                warn!("damaged record data at {data_addr:#x}");
+                info.damaged = true;
+                break;
+            }
+            info.records.push(RecordInfo {
+                file: get_u32(&header, 2),
+                addr: data_addr,
+                len,
+            });
+            info.end += total;
+        }
+        Ok(info)
+    }
+
+    fn scan(&mut self) -> Result<Vec<SectorInfo>> {
+        (0..self.get_sector_count())
+            .map(|x| self.scan_sector(x))
+            .collect()
+    }
+
+    fn erase_sector(&mut self, sector: u32) -> Result<()> {
+        let addr = self.get_sector_addr(sector);
+        self.flash
+            .erase(addr, addr + F::ERASE_SIZE as u32)
+            .map_err(flash_error)
+    }
+
+    /// Erases the sector and starts it as the newest of the log
+    fn start_sector(&mut self, sector: u32, seq: u32) -> Result<WritePosition> {
+        self.erase_sector(sector)?;
+        let mut header = vec![0xFF; Self::get_sector_header_len() as usize];
+        header[0..4].copy_from_slice(&FLASH_SECTOR_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&seq.to_le_bytes());
+        let crc = crc16(&header[..8]);
+        header[8..10].copy_from_slice(&crc.to_le_bytes());
+        self.flash
+            .write(self.get_sector_addr(sector), &header)
+            .map_err(flash_error)?;
+        Ok(WritePosition {
+            sector,
+            seq,
+            offset: Self::get_sector_header_len(),
+        })
+    }
+
+    /// Appends a record to the newest sector, starting the next one if it doesn't fit
+    fn write_record(
+        &mut self,
+        position: &mut Option<WritePosition>,
+        file: u32,
+        data: &[u8],
+    ) -> Result<()> {
+        let header_len = Self::get_record_header_len() as usize;
+        let total = align_up(header_len + data.len(), Self::get_align());
+        let pos = match *position {
+            Some(pos) if pos.offset as usize + total <= F::ERASE_SIZE => pos,
+            Some(pos) => {
+                let sector = (pos.sector + 1) % self.get_sector_count();
+                self.start_sector(sector, pos.seq.wrapping_add(1))?
+            }
+            None => self.start_sector(0, 0)?,
+        };
+        *position = Some(pos);
+
+        let mut record = vec![0xFF; total];
+        record[0..2].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        record[2..6].copy_from_slice(&file.to_le_bytes());
+        record[6..8].copy_from_slice(&crc16(data).to_le_bytes());
+        let crc = crc16(&record[..8]);
+        record[8..10].copy_from_slice(&crc.to_le_bytes());
+        record[header_len..header_len + data.len()].copy_from_slice(data);
+        self.flash
+            .write(self.get_sector_addr(pos.sector) + pos.offset, &record)
+            .map_err(flash_error)?;
+        *position = Some(WritePosition {
+            offset: pos.offset + total as u32,
+            ..pos
+        });
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LogState {
+    /// None until the first sector is started
+    position: Option<WritePosition>,
+    next_file: u32,
+}
+
+/// DsFile writing a circular log of files onto a region of NorFlash.
+///
+/// The oldest sectors are erased when the log wraps, so the oldest files are lost
+/// without any quota. Writes are buffered up to FLASH_MAX_RECORD bytes or until a flush.
+/// The flash has no clock, the modified time listed for a file is its id.
+pub struct FlashDsFile<R: DsFlashRegions> {
+    path: String,
+    prefix: String,
+    name: String,
+    /// id of the open file
+    file: Option<u32>,
+    /// None until the log is read
+    state: Option<LogState>,
+    pending: Vec<u8>,
+    regions: PhantomData<R>,
+}
+
+impl<R: DsFlashRegions> FlashDsFile<R> {
+    fn with_log<T>(path: &str, f: impl FnOnce(&mut FlashLog<R::Flash>) -> Result<T>) -> Result<T> {
+        R::with_region(path, |flash, range| {
+            FlashLog::new(flash, range).and_then(|mut log| f(&mut log))
+        })
+        .unwrap_or_else(|| Err(anyhow!("no flash region for {path}")))
+    }
+
+    /// Finds the newest sector and file id the first time the log is used
+    fn mount(&mut self) -> Result<&mut LogState> {
+        if self.state.is_none() {
+            let sectors = Self::with_log(&self.path, |log| log.scan())?;
+            let position = sectors
+                .iter()
+                .enumerate()
+                .filter_map(|(i, x)| x.seq.map(|seq| (i, seq, x)))
+                .max_by(|a, b| cmp_seq(a.1, b.1))
+                .map(|(i, seq, x)| WritePosition {
+                    sector: i as u32,
+                    seq,
+                    // a damaged sector is full, the next record starts a new one
+                    offset: if x.damaged {
+                        <R::Flash as NorFlash>::ERASE_SIZE as u32
+                    } else {
+                        x.end
+                    },
+                });
+            let next_file = sectors
+                .iter()
+                .flat_map(|x| &x.records)
+                .map(|x| x.file + 1)
+                .max()
+                .unwrap_or(0);
+            self.state = Some(LogState {
+                position,
+                next_file,
+            });
+        }
+        Ok(self.state.as_mut().expect("flash log is mounted"))
+    }
+
+    fn get_file_name(prefix: &str, file: u32) -> String {
+        format!("{prefix}_{file:08}.dat")
+    }
+
+    fn parse_file_name(&self, name: &str) -> Option<u32> {
+        name.strip_prefix(self.prefix.as_str())?
+            .strip_prefix('_')?
+            .strip_suffix(".dat")?
+            .parse()
+            .ok()
+    }
+
+    /// Writes the pending bytes as records, all of them or only whole records
+    fn write_pending(&mut self, all: bool) -> Result<()> {
+        let Some(file) = self.file else {
+            self.pending.clear();
+            return Ok(());
+        };
+        let max = FlashLog::<R::Flash>::get_max_record();
+        let mut state = *self.mount()?;
+        let mut written = 0;
+        let pending = &self.pending;
+        let result = Self::with_log(&self.path, |log| {
+            while pending.len() - written >= max || (all && written < pending.len()) {
+                let len = max.min(pending.len() - written);
+                log.write_record(&mut state.position, file, &pending[written..written + len])?;
+                written += len;
+            }
+            Ok(())
+        });
+        self.pending.drain(..written);
+        // after a failed write the log is read again to find where it ended
+        self.state = result.is_ok().then_some(state);
+        result
+    }
+
+    /// Contents of a file as Ds wrote them, records lost to damage are skipped
+    pub fn read_file(&mut self, name: &str) -> Result<Vec<u8>> {
+        let file = self
+            .parse_file_name(name)
+            .ok_or_else(|| anyhow!("{name} isn't a file of {}", self.path))?;
+        Self::with_log(&self.path, |log| {
+            let mut sectors = log.scan()?;
+            sectors.sort_by(|a, b| match (a.seq, b.seq) {
+                (Some(a), Some(b)) => cmp_seq(a, b),
+                (a, b) => a.cmp(&b),
+            });
+            let mut buf = Vec::new();
+            for record in sectors.iter().flat_map(|x| &x.records) {
+                if record.file == file {
+                    buf.extend(log.read(record.addr, record.len as usize)?);
+                }
+            }
+            Ok(buf)
+        })
+    }
+}
+
+impl<R: DsFlashRegions> Default for FlashDsFile<R> {
+    fn default() -> Self {
+        <Self as DsFile>::new(String::new())
+    }
+}
+
+impl<R: DsFlashRegions> DsFile for FlashDsFile<R> {
+    fn new(dir: String) -> Self {
+        Self {
+            prefix: dir.split("/").last().unwrap_or("unnamed").to_string(),
+            path: dir,
+            name: String::new(),
+            file: None,
+            state: None,
+            pending: Vec::new(),
+            regions: PhantomData,
+        }
+    }
+
+    fn close(&mut self) {
+        if let Err(e) = self.flush() {
+            error!("failed to flush {} before closing {e}", self.name);
+        }
+        self.file = None;
+        self.name.clear();
+    }
+
+    fn open(&mut self) {
+        match self.mount() {
+            Ok(state) => {
+                let file = state.next_file;
+                state.next_file += 1;
+                self.file = Some(file);
+                self.name = Self::get_file_name(&self.prefix, file);
+            }
+            Err(e) => error!("failed to open a file in the flash log {} {e}", self.path),
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.file.is_none() {
+            self.open();
+        }
+        if self.file.is_none() {
+            return Ok(0);
+        }
+
+        self.pending.extend_from_slice(buf);
+        if self.pending.len() >= FlashLog::<R::Flash>::get_max_record() {
+            self.write_pending(false)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_pending(true)
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn list_files(&self) -> Result<Vec<DsFileInfo>> {
+        let sectors = Self::with_log(&self.path, |log| log.scan())?;
+        let mut files = BTreeMap::new();
+        for record in sectors.iter().flat_map(|x| &x.records) {
+            *files.entry(record.file).or_insert(0) += record.len as u64;
+        }
+        Ok(files
+            .into_iter()
+            .map(|(file, size)| DsFileInfo {
+                name: Self::get_file_name(&self.prefix, file),
+                size,
+                modified: file as Timestamp,
+            })
+            .collect())
+    }
+
+    /// Erases the sectors holding only this file and older ones,
+    /// the part of it sharing a sector with a newer file is kept until the log wraps
+    fn delete_file(&mut self, name: &str) -> Result<()> {
+        let file = self
+            .parse_file_name(name)
+            .ok_or_else(|| anyhow!("{name} isn't a file of {}", self.path))?;
+        let current = self.mount()?.position.map(|x| x.sector);
+        Self::with_log(&self.path, |log| {
+            let sectors = log.scan()?;
+            if !sectors
+                .iter()
+                .flat_map(|x| &x.records)
+                .any(|x| x.file == file)
+            {
+                return Err(anyhow!("{name} not found in {}", self.path));
+            }
+            for (i, sector) in sectors.iter().enumerate() {
+                if Some(i as u32) != current
+                    && !sector.records.is_empty()
+                    && sector.records.iter().all(|x| x.file <= file)
+                {
+                    log.erase_sector(i as u32)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Bytes that can be written before the log wraps
+    fn get_free_space(&self) -> Result<u64> {
+        let sector_size = <R::Flash as NorFlash>::ERASE_SIZE as u32;
+        let sector_capacity = sector_size - FlashLog::<R::Flash>::get_sector_header_len();
+        Self::with_log(&self.path, |log| {
+            let sectors = log.scan()?;
+            let newest = sectors
+                .iter()
+                .filter_map(|x| x.seq.map(|seq| (seq, x)))
+                .max_by(|a, b| cmp_seq(a.0, b.0))
+                .map(|(_, x)| x)
+                .map_or(0, |x| if x.damaged { 0 } else { sector_size - x.end });
+            let erased = sectors.iter().filter(|x| x.seq.is_none()).count() as u64;
+            Ok(erased * sector_capacity as u64 + newest as u64)
+        })
+    }
+}
+
+/// NorFlash in RAM for host tests, writes only clear bits like on a real flash
+pub struct RamFlash<const WRITE_SIZE: usize, const ERASE_SIZE: usize> {
+    data: Vec<u8>,
+    erase_counts: Vec<u32>,
+    /// bytes written before writes fail, simulates losing power
+    write_budget: Option<usize>,
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> RamFlash<WRITE_SIZE, ERASE_SIZE> {
+    /// size is rounded down to whole sectors, the flash starts erased
+    pub fn new(size: usize) -> Self {
+        let sectors = size / ERASE_SIZE;
+        Self {
+            data: vec![0xFF; sectors * ERASE_SIZE],
+            erase_counts: vec![0; sectors],
+            write_budget: None,
+        }
+    }
+
+    /// Times each sector was erased
+    pub fn get_erase_counts(&self) -> &[u32] {
+        &self.erase_counts
+    }
+
+    /// Only budget more bytes are written, the write crossing it is cut short and fails.
+    /// None writes everything
+    pub fn set_write_budget(&mut self, budget: Option<usize>) {
+        self.write_budget = budget;
+    }
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> ErrorType
+    for RamFlash<WRITE_SIZE, ERASE_SIZE>
+{
+    type Error = NorFlashErrorKind;
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> ReadNorFlash
+    for RamFlash<WRITE_SIZE, ERASE_SIZE>
+{
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl<const WRITE_SIZE: usize, const ERASE_SIZE: usize> NorFlash
+    for RamFlash<WRITE_SIZE, ERASE_SIZE>
+{
+    const WRITE_SIZE: usize = WRITE_SIZE;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        let (from, to) = (from as usize, to as usize);
+        self.data[from..to].fill(0xFF);
+        for count in &mut self.erase_counts[from / ERASE_SIZE..to / ERASE_SIZE] {
+            *count += 1;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        let len = self
+            .write_budget
+            .map_or(bytes.len(), |x| x.min(bytes.len()));
+        let offset = offset as usize;
+        for (dst, src) in self.data[offset..offset + len].iter_mut().zip(bytes) {
+            *dst &= *src;
+        }
+        if let Some(budget) = &mut self.write_budget {
+            *budget -= len;
+        }
+        if len < bytes.len() {
+            return Err(NorFlashErrorKind::Other);
+        }
+        Ok(())
+    }
+}
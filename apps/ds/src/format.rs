@@ -0,0 +1,201 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use anyhow::{anyhow, Result};
+use bincode::{decode_from_slice, encode_to_vec, Decode, Encode};
+use core::ops::Range;
+use log::*;
+use rfe::{
+    framing::{encode_frame, FrameDecoder, HEADER_SIZE},
+    msg::{Instance, MsgPacket, SCHEMA_HASH},
+    time::Timestamp,
+    BINCODE_CONFIG,
+};
+
+/// "RFDS"
+pub const DS_MAGIC: u32 = 0x5246_4453;
+/// Bumped when the header or record layout changes
+pub const DS_FORMAT_VERSION: u16 = 1;
+
+/// First frame of every ds file, the records follow in their own frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct DsFileHeader {
+    pub magic: u32,
+    pub version: u16,
+    /// rfe::msg::SCHEMA_HASH of the build that wrote the file
+    pub schema_hash: u64,
+    pub instance: Instance,
+    /// system time the file was opened
+    pub start_time: Timestamp,
+}
+
+impl DsFileHeader {
+    pub fn new(instance: Instance, start_time: Timestamp) -> Self {
+        Self {
+            magic: DS_MAGIC,
+            version: DS_FORMAT_VERSION,
+            schema_hash: SCHEMA_HASH,
+            instance,
+            start_time,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        encode_frame(&encode_to_vec(self, BINCODE_CONFIG).expect("failed to serialize header"))
+    }
+}
+
+/// Frames a packet as a record, seq counts the records in a file from 0
+pub fn encode_record(seq: u32, packet: &MsgPacket) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&seq.to_le_bytes());
+    payload.extend(encode_to_vec(packet, BINCODE_CONFIG).expect("failed to serialize ds packet"));
+    encode_frame(&payload)
+}
+
+/// True if the header magic is where a ds file has it, found even if the sync and length
+/// before it are damaged. Files written before the header have neither
+pub fn has_ds_magic(bytes: &[u8]) -> bool {
+    let magic = encode_to_vec(DS_MAGIC, BINCODE_CONFIG).expect("failed to serialize magic");
+    bytes
+        .get(HEADER_SIZE..)
+        .is_some_and(|x| x.starts_with(&magic))
+}
+
+/// Packets read from a ds file and what was lost reading it
+#[derive(Debug, Default)]
+pub struct DsFileContents {
+    /// None if the header was damaged
+    pub header: Option<DsFileHeader>,
+    pub packets: Vec<MsgPacket>,
+    /// sequence numbers of records missing between the records read
+    pub lost_records: Vec<Range<u32>>,
+    /// records with a good crc that didn't decode, such as from a different schema
+    pub undecodable_records: u32,
+    pub crc_errors: u32,
+    /// bytes skipped resyncing, from damaged records or a record cut short at the end
+    pub dropped_bytes: u32,
+}
+
+impl DsFileContents {
+    fn read_header(&mut self, payload: &[u8]) -> Result<bool> {
+        let Ok((header, _)) = decode_from_slice::<DsFileHeader, _>(payload, BINCODE_CONFIG) else {
+            return Ok(false);
+        };
+        if header.magic != DS_MAGIC {
+            return Ok(false);
+        }
+        if header.version != DS_FORMAT_VERSION {
+            return Err(anyhow!("unsupported ds format version {}", header.version));
+        }
+        if header.schema_hash != SCHEMA_HASH {
+            warn!(
+                "file schema {:#x} doesn't match {SCHEMA_HASH:#x}, records may not decode",
+                header.schema_hash
+            );
+        }
+        self.header = Some(header);
+        Ok(true)
+    }
+
+    /// next_seq is the sequence number expected next
+    fn read_record(&mut self, payload: &[u8], next_seq: &mut u32) -> Option<MsgPacket> {
+        if payload.len() < 4 {
+            self.undecodable_records += 1;
+            return None;
+        }
+        let seq = u32::from_le_bytes(payload[..4].try_into().unwrap());
+        if seq > *next_seq {
+            self.lost_records.push(*next_seq..seq);
+        }
+        *next_seq = (*next_seq).max(seq + 1);
+        match decode_from_slice::<MsgPacket, _>(&payload[4..], BINCODE_CONFIG) {
+            Ok((packet, _)) => Some(packet),
+            Err(_) => {
+                self.undecodable_records += 1;
+                None
+            }
+        }
+    }
+}
+
+/// Reads a ds file pushed in chunks, so it never has to be in memory at once.
+/// Packets are taken as they're decoded, the contents hold everything else
+#[derive(Debug, Default)]
+pub struct DsFileDecoder {
+    decoder: FrameDecoder,
+    contents: DsFileContents,
+    next_seq: u32,
+    read_first: bool,
+    finished: bool,
+}
+
+impl DsFileDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the next bytes of the file
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.decoder.push(bytes);
+    }
+
+    /// Marks the end of the file, a partial frame left is skipped instead of waited on
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// None if the header hasn't been read yet or was damaged
+    pub fn get_header(&self) -> Option<&DsFileHeader> {
+        self.contents.header.as_ref()
+    }
+
+    /// Returns the next packet or None if more bytes are needed
+    pub fn next_packet(&mut self) -> Result<Option<MsgPacket>> {
+        loop {
+            while let Some(payload) = self.decoder.next_frame() {
+                if !core::mem::replace(&mut self.read_first, true) {
+                    if self.contents.read_header(&payload)? {
+                        continue;
+                    }
+                    warn!("ds file header missing or damaged");
+                }
+                if let Some(packet) = self.contents.read_record(&payload, &mut self.next_seq) {
+                    return Ok(Some(packet));
+                }
+            }
+
+            // the file ends in a partial frame, cut short or with a damaged length
+            let pending = self.decoder.get_pending_bytes();
+            if !self.finished || pending == 0 {
+                return Ok(None);
+            }
+            self.decoder.skip_frame();
+            if self.decoder.get_pending_bytes() == pending {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// What was read, without the packets already taken
+    pub fn into_contents(self) -> DsFileContents {
+        let mut contents = self.contents;
+        contents.crc_errors = self.decoder.get_crc_errors();
+        contents.dropped_bytes =
+            self.decoder.get_dropped_bytes() + self.decoder.get_pending_bytes();
+        contents
+    }
+}
+
+/// Reads a file written by Ds, damaged records are skipped
+pub fn decode_ds_file(bytes: &[u8]) -> Result<DsFileContents> {
+    let mut decoder = DsFileDecoder::new();
+    decoder.push(bytes);
+    decoder.finish();
+    let mut packets = Vec::new();
+    while let Some(packet) = decoder.next_packet()? {
+        packets.push(packet);
+    }
+    let mut contents = decoder.into_contents();
+    contents.packets = packets;
+    Ok(contents)
+}
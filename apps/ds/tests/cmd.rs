@@ -0,0 +1,31 @@
+//! Ds commands and their statuses
+
+mod common;
+
+use common::*;
+use ds::*;
+use rfe::msg::*;
+
+#[test]
+fn flush_is_accepted_then_executed_after_the_cycle_is_written() {
+    let mut ds = Ds::<MemDsFile>::new(
+        tlm_sets([source_set(0, "example", DsFileSettings::default())]),
+        true,
+    );
+    run_ds(&mut ds, &[2, 1], |step, out_data| match step {
+        0 => {
+            assert!(out_data.files[0].pending_bytes > 0);
+            send_cmd(DsCmd::Flush);
+        }
+        _ => assert_eq!(out_data.files[0].pending_bytes, 0),
+    });
+
+    let statuses = get_cmd_statuses();
+    assert_eq!(
+        statuses.iter().map(|x| x.state).collect::<Vec<_>>(),
+        vec![CmdState::Accepted, CmdState::Executed]
+    );
+    assert!(statuses
+        .iter()
+        .all(|x| x.cmd == MsgKind::DsCmd && x.cmd_timestamp == statuses[0].cmd_timestamp));
+}
@@ -0,0 +1,264 @@
+//! In-memory DsFile and apps to drive Ds on simulated time
+// each test binary uses a different part of it
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use anyhow::{anyhow, Result};
+use ds::*;
+use hashbrown::HashMap;
+use rfe::{msg::*, time::SimTimeDriver, App, Rate, Rfe, RfeInstance};
+
+#[derive(Debug, Default, Clone)]
+pub struct MemFile {
+    pub dir: String,
+    pub name: String,
+    pub data: Vec<u8>,
+    pub deleted: bool,
+    /// order of the last write
+    pub modified: u64,
+}
+
+thread_local! {
+    /// every file opened on this thread
+    static FILES: RefCell<Vec<MemFile>> = const { RefCell::new(Vec::new()) };
+    static WRITES: Cell<u64> = const { Cell::new(0) };
+    /// bytes the files of this thread may use
+    static DISK_SIZE: Cell<u64> = const { Cell::new(u64::MAX) };
+    static OPEN_FAILS: Cell<bool> = const { Cell::new(false) };
+    /// events sent by ds
+    static EVENTS: RefCell<Vec<Event>> = const { RefCell::new(Vec::new()) };
+    static CMD_STATUSES: RefCell<Vec<CmdStatus>> = const { RefCell::new(Vec::new()) };
+    /// msgs the monitor sends on its next run
+    static TO_SEND: RefCell<Vec<Msg>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Files that haven't been deleted
+pub fn get_files() -> Vec<MemFile> {
+    FILES.with_borrow(|files| files.iter().filter(|x| !x.deleted).cloned().collect())
+}
+
+/// Every file opened, including deleted ones
+pub fn get_all_files() -> Vec<MemFile> {
+    FILES.with_borrow(|files| files.clone())
+}
+
+pub fn set_disk_size(size: u64) {
+    DISK_SIZE.set(size);
+}
+
+/// Opening fails like a StdDsFile that can't create its file, writes return 0
+pub fn set_open_fails(fails: bool) {
+    OPEN_FAILS.set(fails);
+}
+
+pub fn get_events() -> Vec<Event> {
+    EVENTS.with_borrow(|events| events.clone())
+}
+
+pub fn get_cmd_statuses() -> Vec<CmdStatus> {
+    CMD_STATUSES.with_borrow(|statuses| statuses.clone())
+}
+
+/// Sent to ds from the monitor within a tick
+pub fn send_cmd(cmd: DsCmd) {
+    TO_SEND.with_borrow_mut(|msgs| msgs.push(Msg::DsCmd(cmd)));
+}
+
+#[derive(Debug, Default)]
+pub struct MemDsFile {
+    dir: String,
+    index: Option<usize>,
+}
+
+impl DsFile for MemDsFile {
+    fn new(dir: String) -> Self {
+        Self { dir, index: None }
+    }
+
+    fn close(&mut self) {
+        self.index = None;
+    }
+
+    fn open(&mut self) {
+        if OPEN_FAILS.get() {
+            return;
+        }
+        FILES.with_borrow_mut(|files| {
+            files.push(MemFile {
+                dir: self.dir.clone(),
+                name: format!("{}_{}", self.dir, files.len()),
+                ..Default::default()
+            });
+            self.index = Some(files.len() - 1);
+        });
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.index.is_none() {
+            self.open();
+        }
+        let Some(index) = self.index else {
+            return Ok(0);
+        };
+        let modified = WRITES.get();
+        WRITES.set(modified + 1);
+        FILES.with_borrow_mut(|files| {
+            files[index].data.extend_from_slice(buf);
+            files[index].modified = modified;
+        });
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_name(&self) -> String {
+        self.index
+            .map(|i| FILES.with_borrow(|files| files[i].name.clone()))
+            .unwrap_or_default()
+    }
+
+    fn list_files(&self) -> Result<Vec<DsFileInfo>> {
+        let mut files = get_files()
+            .into_iter()
+            .filter(|x| x.dir == self.dir)
+            .map(|x| DsFileInfo {
+                name: x.name,
+                size: x.data.len() as u64,
+                modified: x.modified,
+            })
+            .collect::<Vec<_>>();
+        files.sort_by_key(|x| x.modified);
+        Ok(files)
+    }
+
+    fn delete_file(&mut self, name: &str) -> Result<()> {
+        FILES.with_borrow_mut(|files| {
+            let file = files
+                .iter_mut()
+                .find(|x| x.name == name && !x.deleted)
+                .ok_or_else(|| anyhow!("{name} not found"))?;
+            file.deleted = true;
+            Ok(())
+        })
+    }
+
+    fn get_free_space(&self) -> Result<u64> {
+        let used = get_files().iter().map(|x| x.data.len() as u64).sum::<u64>();
+        Ok(DISK_SIZE.get().saturating_sub(used))
+    }
+}
+
+/// Sends an incrementing counter at 10 Hz
+pub struct Source {
+    pub counter: u32,
+}
+
+impl App for Source {
+    fn init(&mut self, _rfe: &mut Rfe) -> Result<()> {
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        self.counter += 1;
+        rfe.send(Msg::ExampleOutData(ExampleOutData {
+            counter: self.counter,
+        }));
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz10
+    }
+}
+
+pub struct Monitor {
+    pub out_data: Rc<RefCell<Option<DsOutData>>>,
+}
+
+impl App for Monitor {
+    fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
+        rfe.subscribe(TargetMsg::new(Instance::All, MsgKind::DsOutData));
+        rfe.subscribe(TargetMsg::new(Instance::All, MsgKind::Event));
+        rfe.subscribe(TargetMsg::new(Instance::All, MsgKind::CmdStatus));
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        while let Some(msg) = rfe.recv() {
+            match &msg.msg {
+                Msg::DsOutData(data) => *self.out_data.borrow_mut() = Some(data.clone()),
+                Msg::Event(event) => EVENTS.with_borrow_mut(|events| events.push(event.clone())),
+                Msg::CmdStatus(status) => CMD_STATUSES.with_borrow_mut(|x| x.push(*status)),
+                _ => {}
+            }
+        }
+        for msg in TO_SEND.take() {
+            rfe.send(msg);
+        }
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+/// Tlm set recording the source to path
+pub fn source_set(id: TlmSetId, path: &str, file_settings: DsFileSettings) -> DsTlmSet {
+    DsTlmSet {
+        items: vec![TlmSetItem {
+            counter: 0,
+            target: TargetMsg::new(Instance::All, MsgKind::ExampleOutData),
+            decimation: 0,
+        }],
+        id,
+        enabled: true,
+        path: path.to_string(),
+        file_settings,
+        flush_policy: DsFlushPolicy::Buffered,
+        quota: 0,
+        low_priority: false,
+    }
+}
+
+pub fn tlm_sets(sets: impl IntoIterator<Item = DsTlmSet>) -> HashMap<TlmSetId, DsTlmSet> {
+    sets.into_iter().map(|x| (x.id, x)).collect()
+}
+
+/// Runs the source and ds together, each step runs for the given seconds and is
+/// passed the last DsOutData
+pub fn run_ds<F: DsFile>(
+    ds: &mut Ds<F>,
+    steps: &[u64],
+    mut on_step: impl FnMut(usize, &DsOutData),
+) {
+    let time_driver = SimTimeDriver::new();
+    let out_data = Rc::new(RefCell::new(None));
+    let mut source = Source { counter: 0 };
+    let mut monitor = Monitor {
+        out_data: out_data.clone(),
+    };
+    let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+    instance.add_app("source", &mut source).unwrap();
+    instance.add_app("DS", ds).unwrap();
+    instance.add_app("monitor", &mut monitor).unwrap();
+    // ds is phased to run on the last tick of every second,
+    // one more tick passes its out data to the monitor
+    instance.step(1);
+    for (i, seconds) in steps.iter().enumerate() {
+        instance.step(seconds * 100);
+        on_step(i, out_data.borrow().as_ref().unwrap());
+    }
+}
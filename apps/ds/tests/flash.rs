@@ -0,0 +1,183 @@
+//! FlashDsFile logs on a RAM flash
+
+mod common;
+
+use common::*;
+use ds::*;
+use embedded_storage::nor_flash::NorFlash;
+use rfe::{framing::crc16, msg::*};
+use std::{cell::RefCell, ops::Range};
+
+type TestFlash = RamFlash<4, 1024>;
+
+thread_local! {
+    static FLASH: RefCell<TestFlash> = RefCell::new(TestFlash::new(20 * 1024));
+}
+
+struct TestRegions;
+
+impl DsFlashRegions for TestRegions {
+    type Flash = TestFlash;
+
+    fn with_region<T>(path: &str, f: impl FnOnce(&mut TestFlash, Range<u32>) -> T) -> Option<T> {
+        let range = match path {
+            "log/example" => 0..12 * 1024,
+            "log/small" => 12 * 1024..16 * 1024,
+            "log/wrap" => 16 * 1024..20 * 1024,
+            _ => return None,
+        };
+        Some(FLASH.with_borrow_mut(|flash| f(flash, range)))
+    }
+}
+
+type TestDsFile = FlashDsFile<TestRegions>;
+
+fn with_flash<T>(f: impl FnOnce(&mut TestFlash) -> T) -> T {
+    FLASH.with_borrow_mut(f)
+}
+
+/// Writes a file of len bytes all set to its index
+fn write_file(file: &mut TestDsFile, index: u8, len: usize) -> String {
+    file.open();
+    file.write(&vec![index; len]).unwrap();
+    let name = file.get_name();
+    file.close();
+    name
+}
+
+#[test]
+fn records_ds_files() {
+    let mut set = source_set(
+        0,
+        "log/example",
+        DsFileSettings {
+            max_size: 1000,
+            max_age: 0,
+            enabled: true,
+        },
+    );
+    set.flush_policy = DsFlushPolicy::EveryPacket;
+    let mut ds = Ds::<TestDsFile>::new(tlm_sets([set]), true);
+    let mut out_data = None;
+    run_ds(&mut ds, &[10], |_, x| out_data = Some(x.clone()));
+    let out_data = out_data.unwrap();
+
+    // read back after a reboot
+    let mut file = TestDsFile::new("log/example".to_string());
+    let files = file.list_files().unwrap();
+    assert!(files.len() > 2);
+    assert_eq!(files.last().unwrap().name, out_data.files[0].name);
+    let mut counters = Vec::new();
+    for info in &files {
+        let buf = file.read_file(&info.name).unwrap();
+        assert_eq!(buf.len() as u64, info.size);
+        let contents = decode_ds_file(&buf).unwrap();
+        assert!(contents.header.is_some());
+        assert!(contents.lost_records.is_empty());
+        counters.extend(contents.packets.into_iter().map(|x| match x.msg {
+            Msg::ExampleOutData(data) => data.counter,
+            msg => panic!("unexpected msg {msg:?}"),
+        }));
+    }
+    assert_eq!(counters, (1..=counters.len() as u32).collect::<Vec<_>>());
+    assert!(counters.len() >= 90);
+}
+
+#[test]
+fn wraps_around_spreading_erases() {
+    let mut file = TestDsFile::new("log/small".to_string());
+    let names = (0..40)
+        .map(|i| write_file(&mut file, i, 300))
+        .collect::<Vec<_>>();
+
+    // 3 files fit a sector, the oldest sector is erased for each 3 written
+    let files = file.list_files().unwrap();
+    assert!(files.len() >= 9 && files.len() <= 12);
+    assert_eq!(
+        files.iter().map(|x| x.name.clone()).collect::<Vec<_>>(),
+        names[names.len() - files.len()..]
+    );
+    assert_eq!(file.read_file(&names[39]).unwrap(), vec![39; 300]);
+
+    // a new instance continues after the newest sector
+    let mut file = TestDsFile::new("log/small".to_string());
+    for i in 40..52 {
+        write_file(&mut file, i, 300);
+    }
+    assert!(file.list_files().unwrap()[0].name > names[39]);
+    let counts = with_flash(|flash| flash.get_erase_counts()[12..16].to_vec());
+    let (min, max) = (counts.iter().min().unwrap(), counts.iter().max().unwrap());
+    assert!(max - min <= 1, "uneven erases {counts:?}");
+}
+
+#[test]
+fn recovers_from_power_loss() {
+    let mut file = TestDsFile::new("log/small".to_string());
+    file.open();
+    file.write(&[1; 300]).unwrap();
+    file.flush().unwrap();
+    // power is lost writing the second record
+    with_flash(|flash| flash.set_write_budget(Some(100)));
+    file.write(&[2; 300]).unwrap();
+    assert!(file.flush().is_err());
+    let first = file.get_name();
+    with_flash(|flash| flash.set_write_budget(None));
+
+    let mut file = TestDsFile::new("log/small".to_string());
+    assert_eq!(file.read_file(&first).unwrap(), vec![1; 300]);
+    // nothing is written after the damaged record, the next file starts a new sector
+    let second = write_file(&mut file, 3, 300);
+    assert_ne!(first, second);
+    assert_eq!(file.read_file(&first).unwrap(), vec![1; 300]);
+    assert_eq!(file.read_file(&second).unwrap(), vec![3; 300]);
+    let free = file.get_free_space().unwrap();
+    assert_eq!(free, 2 * (1024 - 12) + 1024 - 12 - 312);
+}
+
+#[test]
+fn deletes_oldest_files() {
+    let mut file = TestDsFile::new("log/small".to_string());
+    let free = file.get_free_space().unwrap();
+    assert_eq!(free, 4 * (1024 - 12));
+    let names = (0..3)
+        .map(|i| write_file(&mut file, i, 900))
+        .collect::<Vec<_>>();
+    let free = file.get_free_space().unwrap();
+
+    file.delete_file(&names[0]).unwrap();
+    assert_eq!(file.get_free_space().unwrap(), free + 1024 - 12);
+    let files = file.list_files().unwrap();
+    assert_eq!(
+        files.iter().map(|x| x.name.clone()).collect::<Vec<_>>(),
+        names[1..]
+    );
+    assert!(file.delete_file(&names[0]).is_err());
+    assert!(file.delete_file("example_00000000.dat").is_err());
+}
+
+#[test]
+fn finds_the_newest_sector_after_seq_wraps() {
+    // start the log just before the sector seq wraps
+    let mut header = vec![0xFF; 12];
+    header[0..4].copy_from_slice(&FLASH_SECTOR_MAGIC.to_le_bytes());
+    header[4..8].copy_from_slice(&(u32::MAX - 1).to_le_bytes());
+    let crc = crc16(&header[..8]);
+    header[8..10].copy_from_slice(&crc.to_le_bytes());
+    with_flash(|flash| flash.write(16 * 1024, &header).unwrap());
+
+    let mut file = TestDsFile::new("log/wrap".to_string());
+    let names = (0..9)
+        .map(|i| write_file(&mut file, i, 300))
+        .collect::<Vec<_>>();
+
+    // sectors hold seq u32::MAX - 1 to 0, a new instance continues after seq 0
+    let mut file = TestDsFile::new("log/wrap".to_string());
+    let next = write_file(&mut file, 9, 300);
+    assert!(next > names[8]);
+    let files = file.list_files().unwrap();
+    assert_eq!(files.last().unwrap().name, next);
+    assert_eq!(file.read_file(&names[8]).unwrap(), vec![8; 300]);
+    assert_eq!(file.read_file(&next).unwrap(), vec![9; 300]);
+    // every sector is used, only the newest with seq 1 has space left
+    assert_eq!(file.get_free_space().unwrap(), 1024 - 12 - 312);
+}
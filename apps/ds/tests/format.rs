@@ -0,0 +1,140 @@
+//! Reading ds files with damaged records
+
+use ds::*;
+use rfe::{framing::SYNC, msg::*};
+
+fn packet(counter: u32) -> MsgPacket {
+    MsgPacket {
+        instance: Instance::Example,
+        msg: Msg::ExampleOutData(ExampleOutData { counter }),
+        timestamp: counter as u64 * 100_000,
+    }
+}
+
+/// A file of 10 records, with the offset each record starts at
+fn write_file() -> (Vec<u8>, Vec<usize>) {
+    let mut file = DsFileHeader::new(Instance::Example, 1_700_000_000_000_000).encode();
+    let mut offsets = Vec::new();
+    for seq in 0..10 {
+        offsets.push(file.len());
+        file.extend(encode_record(seq, &packet(seq + 1)));
+    }
+    (file, offsets)
+}
+
+fn counters(contents: &DsFileContents) -> Vec<u32> {
+    contents
+        .packets
+        .iter()
+        .map(|x| match x.msg {
+            Msg::ExampleOutData(data) => data.counter,
+            _ => panic!("unexpected msg"),
+        })
+        .collect()
+}
+
+#[test]
+fn reads_header_and_records() {
+    let (file, _) = write_file();
+    let contents = decode_ds_file(&file).unwrap();
+    let header = contents.header.unwrap();
+    assert_eq!(header.magic, DS_MAGIC);
+    assert_eq!(header.version, DS_FORMAT_VERSION);
+    assert_eq!(header.schema_hash, SCHEMA_HASH);
+    assert_eq!(header.instance, Instance::Example);
+    assert_eq!(header.start_time, 1_700_000_000_000_000);
+    assert_eq!(counters(&contents), (1..=10).collect::<Vec<_>>());
+    assert!(contents.lost_records.is_empty());
+    assert_eq!(contents.dropped_bytes, 0);
+}
+
+#[test]
+fn skips_damaged_records() {
+    let (mut file, offsets) = write_file();
+    // corrupt the payload of record 3 and the sync marker of record 6
+    file[offsets[3] + 8] ^= 0xFF;
+    file[offsets[6]] = 0;
+
+    let contents = decode_ds_file(&file).unwrap();
+    assert!(contents.header.is_some());
+    assert_eq!(counters(&contents), vec![1, 2, 3, 5, 6, 8, 9, 10]);
+    assert_eq!(contents.lost_records, vec![3..4, 6..7]);
+    assert_eq!(contents.crc_errors, 1);
+    let lost_bytes = offsets[4] - offsets[3] + offsets[7] - offsets[6];
+    assert_eq!(contents.dropped_bytes, lost_bytes as u32);
+}
+
+#[test]
+fn resyncs_after_a_damaged_length() {
+    let (mut file, offsets) = write_file();
+    // a length longer than the rest of the file
+    file[offsets[8] + SYNC.len()] = 0xFF;
+
+    let contents = decode_ds_file(&file).unwrap();
+    assert_eq!(counters(&contents), vec![1, 2, 3, 4, 5, 6, 7, 8, 10]);
+    assert_eq!(contents.lost_records, vec![8..9]);
+}
+
+#[test]
+fn reports_a_truncated_last_record() {
+    let (file, offsets) = write_file();
+    let cut = offsets[9] + 5;
+
+    let contents = decode_ds_file(&file[..cut]).unwrap();
+    assert_eq!(counters(&contents), (1..=9).collect::<Vec<_>>());
+    assert!(contents.lost_records.is_empty());
+    assert_eq!(contents.dropped_bytes, 5);
+}
+
+#[test]
+fn reads_records_without_a_header() {
+    let (file, offsets) = write_file();
+    let contents = decode_ds_file(&file[offsets[0]..]).unwrap();
+    assert!(contents.header.is_none());
+    assert_eq!(counters(&contents), (1..=10).collect::<Vec<_>>());
+}
+
+#[test]
+fn finds_the_magic_of_a_damaged_header() {
+    let (mut file, _) = write_file();
+    assert!(has_ds_magic(&file));
+    file[0] = 0;
+    file[1] = 0;
+    assert!(has_ds_magic(&file));
+    let contents = decode_ds_file(&file).unwrap();
+    assert!(contents.header.is_none());
+    assert_eq!(counters(&contents), (1..=10).collect::<Vec<_>>());
+
+    // files from before the header are packets back to back
+    let legacy = (1..=10)
+        .flat_map(|x| bincode::encode_to_vec(packet(x), rfe::BINCODE_CONFIG).unwrap())
+        .collect::<Vec<_>>();
+    assert!(!has_ds_magic(&legacy));
+}
+
+#[test]
+fn decodes_a_file_pushed_in_chunks() {
+    let (mut file, offsets) = write_file();
+    file[offsets[3] + 8] ^= 0xFF;
+    let cut = offsets[9] + 5;
+
+    let mut decoder = DsFileDecoder::new();
+    let mut packets = Vec::new();
+    for b in &file[..cut] {
+        decoder.push(&[*b]);
+        while let Some(packet) = decoder.next_packet().unwrap() {
+            packets.push(packet);
+        }
+    }
+    assert!(decoder.get_header().is_some());
+    decoder.finish();
+    assert!(decoder.next_packet().unwrap().is_none());
+
+    let mut contents = decoder.into_contents();
+    assert!(contents.packets.is_empty());
+    contents.packets = packets;
+    assert_eq!(counters(&contents), vec![1, 2, 3, 5, 6, 7, 8, 9]);
+    assert_eq!(contents.lost_records, vec![3..4]);
+    assert_eq!(contents.crc_errors, 1);
+    assert_eq!(contents.dropped_bytes, (offsets[4] - offsets[3] + 5) as u32);
+}
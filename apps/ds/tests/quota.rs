@@ -0,0 +1,167 @@
+//! Ds quotas and low disk suspension, recorded to in-memory files on simulated time
+
+mod common;
+
+use common::*;
+use ds::*;
+use rfe::msg::EventSeverity;
+
+/// Rotates every few records so there are old files to delete
+const SMALL_FILES: DsFileSettings = DsFileSettings {
+    max_size: 200,
+    max_age: 0,
+    enabled: true,
+};
+
+fn dir_bytes(dir: &str) -> u64 {
+    get_files()
+        .iter()
+        .filter(|x| x.dir == dir)
+        .map(|x| x.data.len() as u64)
+        .sum()
+}
+
+#[test]
+fn deletes_oldest_files_over_the_set_quota() {
+    let mut limited = source_set(0, "limited", SMALL_FILES);
+    limited.quota = 1000;
+    let unlimited = source_set(1, "unlimited", SMALL_FILES);
+    let mut ds = Ds::<MemDsFile>::new(tlm_sets([limited, unlimited]), true);
+    let mut out_data = None;
+    run_ds(&mut ds, &[60], |_, x| out_data = Some(x.clone()));
+    let out_data = out_data.unwrap();
+
+    let all = get_all_files();
+    let limited = all
+        .iter()
+        .filter(|x| x.dir == "limited")
+        .collect::<Vec<_>>();
+    // the deleted files are the oldest
+    let deleted = limited.iter().take_while(|x| x.deleted).count();
+    assert!(deleted > 0);
+    assert!(limited[deleted..].iter().all(|x| !x.deleted));
+    assert!(all
+        .iter()
+        .filter(|x| x.dir == "unlimited")
+        .all(|x| !x.deleted));
+
+    let status = &out_data.files[0];
+    assert_eq!(status.deleted_files, deleted as u32);
+    assert!(status.dir_bytes <= 1000);
+    assert_eq!(out_data.files[1].deleted_files, 0);
+    // only what was written since the last check is over the quota
+    assert!(dir_bytes("limited") < dir_bytes("unlimited") / 2);
+    let events = get_events();
+    assert!(!events.is_empty());
+    assert!(events
+        .iter()
+        .all(|x| x.severity == EventSeverity::Warn && x.text.contains("limited")));
+}
+
+#[test]
+fn counts_sets_sharing_a_path_once() {
+    let mut first = source_set(0, "shared", SMALL_FILES);
+    first.quota = 1000;
+    let second = source_set(1, "shared", SMALL_FILES);
+    let mut ds = Ds::<MemDsFile>::new_with_quota(
+        tlm_sets([first, second]),
+        true,
+        DsQuota {
+            max_total_size: 2000,
+            min_free_space: 0,
+        },
+    );
+    let mut out_data = None;
+    run_ds(&mut ds, &[60], |_, x| out_data = Some(x.clone()));
+    let out_data = out_data.unwrap();
+
+    // both sets see the whole path and the total isn't counted twice
+    assert_eq!(out_data.files[0].dir_bytes, out_data.files[1].dir_bytes);
+    assert_eq!(out_data.total_bytes, out_data.files[0].dir_bytes);
+    assert!(out_data.total_bytes <= 1000);
+    // neither set's open file was deleted by the other's quota
+    let all = get_all_files();
+    for status in &out_data.files {
+        let file = all.iter().find(|x| x.name == status.name).unwrap();
+        assert!(!file.deleted);
+    }
+    assert!(all.iter().any(|x| x.deleted));
+}
+
+#[test]
+fn deletes_oldest_files_of_any_set_over_the_total_quota() {
+    let mut ds = Ds::<MemDsFile>::new_with_quota(
+        tlm_sets([
+            source_set(0, "a", SMALL_FILES),
+            source_set(1, "b", SMALL_FILES),
+        ]),
+        true,
+        DsQuota {
+            max_total_size: 3000,
+            min_free_space: 0,
+        },
+    );
+    let mut out_data = None;
+    run_ds(&mut ds, &[60], |_, x| out_data = Some(x.clone()));
+    let out_data = out_data.unwrap();
+
+    let all = get_all_files();
+    let newest_deleted = all.iter().filter(|x| x.deleted).map(|x| x.modified).max();
+    let oldest_kept = all.iter().filter(|x| !x.deleted).map(|x| x.modified).min();
+    assert!(newest_deleted.unwrap() < oldest_kept.unwrap());
+    // both sets record at the same rate so both lose files
+    assert!(out_data.files.iter().all(|x| x.deleted_files > 0));
+    assert!(out_data.total_bytes <= 3000);
+}
+
+#[test]
+fn suspends_low_priority_sets_while_disk_is_low() {
+    let critical = source_set(0, "critical", DsFileSettings::default());
+    let mut low = source_set(1, "low", DsFileSettings::default());
+    low.low_priority = true;
+    let mut ds = Ds::<MemDsFile>::new_with_quota(
+        tlm_sets([critical, low]),
+        true,
+        DsQuota {
+            max_total_size: 0,
+            min_free_space: 1000,
+        },
+    );
+
+    let mut low_bytes = Vec::new();
+    run_ds(&mut ds, &[20, 30, 30, 30], |step, out_data| {
+        low_bytes.push(dir_bytes("low"));
+        match step {
+            0 => {
+                assert!(!out_data.low_disk);
+                // leave less than the min free space
+                set_disk_size(dir_bytes("critical") + dir_bytes("low") + 500);
+            }
+            1 | 2 => {
+                assert!(out_data.low_disk);
+                assert!(!out_data.files[0].suspended);
+                assert!(out_data.files[1].suspended);
+                if step == 2 {
+                    set_disk_size(u64::MAX);
+                }
+            }
+            _ => {
+                assert!(!out_data.low_disk);
+                assert!(!out_data.files[1].suspended);
+            }
+        }
+    });
+    // nothing is recorded while suspended, the low set resumes in a new file
+    assert_eq!(low_bytes[1], low_bytes[2]);
+    assert!(low_bytes[3] > low_bytes[2]);
+    let low_files = get_files().into_iter().filter(|x| x.dir == "low").count();
+    assert_eq!(low_files, 2);
+    let events = get_events()
+        .into_iter()
+        .map(|x| (x.severity, x.text.contains("resuming")))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        vec![(EventSeverity::Warn, false), (EventSeverity::Info, true)]
+    );
+}
@@ -0,0 +1,127 @@
+//! Ds file rotation, recorded to in-memory files on simulated time
+
+mod common;
+
+use common::*;
+use ds::*;
+use rfe::msg::*;
+
+/// Records the source for the given seconds, returns the last DsOutData
+fn record(file_settings: DsFileSettings, seconds: u64) -> DsOutData {
+    let mut ds = Ds::<MemDsFile>::new(tlm_sets([source_set(0, "example", file_settings)]), true);
+    let mut out_data = None;
+    run_ds(&mut ds, &[seconds], |_, x| out_data = Some(x.clone()));
+    out_data.unwrap()
+}
+
+/// Counters of the records in a file
+fn decode_counters(buf: &[u8]) -> Vec<u32> {
+    let contents = decode_ds_file(buf).unwrap();
+    assert!(contents.header.is_some());
+    assert!(contents.lost_records.is_empty());
+    contents
+        .packets
+        .into_iter()
+        .map(|x| match x.msg {
+            Msg::ExampleOutData(data) => data.counter,
+            msg => panic!("unexpected msg {msg:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn rotates_by_size() {
+    let out_data = record(
+        DsFileSettings {
+            max_size: 100,
+            max_age: 0,
+            enabled: true,
+        },
+        20,
+    );
+
+    let files = get_files();
+    assert!(files.len() > 2);
+    for file in &files {
+        assert!(!file.data.is_empty() && file.data.len() <= 100);
+    }
+    // every record is whole and in order across the files
+    let counters = files
+        .iter()
+        .flat_map(|x| decode_counters(&x.data))
+        .collect::<Vec<_>>();
+    assert_eq!(counters, (1..=counters.len() as u32).collect::<Vec<_>>());
+
+    let total = files.iter().map(|x| x.data.len() as u32).sum::<u32>();
+    assert_eq!(out_data.bytes_written, total);
+    let last = files.last().unwrap();
+    assert_eq!(
+        out_data.files,
+        vec![DsFileStatus {
+            id: 0,
+            name: last.name.clone(),
+            bytes: last.data.len() as u32,
+            rotations: files.len() as u32 - 1,
+            // nothing is flushed with the buffered policy
+            pending_bytes: last.data.len() as u32,
+            flush_time: 0,
+            max_flush_time: 0,
+            dir_bytes: out_data.files[0].dir_bytes,
+            deleted_files: 0,
+            open_errors: 0,
+            suspended: false,
+        }]
+    );
+}
+
+#[test]
+fn rotates_by_age() {
+    let out_data = record(
+        DsFileSettings {
+            max_size: 0,
+            max_age: 2,
+            enabled: true,
+        },
+        10,
+    );
+
+    let files = get_files();
+    // the first file is opened at 1 s, then every 2 s
+    assert_eq!(files.len(), 5);
+    for file in &files[..4] {
+        assert_eq!(decode_counters(&file.data).len(), 20);
+    }
+    let last = files.last().unwrap();
+    assert_eq!(out_data.files[0].name, last.name);
+    assert_eq!(out_data.files[0].bytes, last.data.len() as u32);
+    assert_eq!(out_data.files[0].rotations, 4);
+}
+
+#[test]
+fn disabled_settings_keep_one_file() {
+    let out_data = record(
+        DsFileSettings {
+            max_size: 100,
+            max_age: 2,
+            enabled: false,
+        },
+        10,
+    );
+
+    let files = get_files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(out_data.files[0].rotations, 0);
+    assert_eq!(out_data.files[0].bytes, files[0].data.len() as u32);
+}
+
+#[test]
+fn counts_files_that_fail_to_open() {
+    set_open_fails(true);
+    let out_data = record(DsFileSettings::default(), 2);
+
+    assert!(get_all_files().is_empty());
+    // every record tries to open a file
+    assert_eq!(out_data.files[0].open_errors, 20);
+    assert_eq!(out_data.files[0].bytes, 0);
+    assert_eq!(out_data.bytes_written, 0);
+}
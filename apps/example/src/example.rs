@@ -36,16 +36,19 @@ impl App for Example {
         self.data.out_data.counter += 1;
         info!("example running {:?}", self.data);
         while let Some(msg) = rfe.recv() {
-            match msg.msg {
-                Msg::ExampleCmd(cmd) => match cmd {
-                    ExampleCmd::Noop => info!("NOOP command received"),
-                    ExampleCmd::Reset => {
-                        info!("RESET command received");
-                        let perf = self.data.hk.perf;
-                        self.data = Default::default();
-                        self.data.hk.perf = perf;
+            match &msg.msg {
+                Msg::ExampleCmd(cmd) => {
+                    match cmd {
+                        ExampleCmd::Noop => info!("NOOP command received"),
+                        ExampleCmd::Reset => {
+                            info!("RESET command received");
+                            let perf = self.data.hk.perf;
+                            self.data = Default::default();
+                            self.data.hk.perf = perf;
+                        }
                     }
-                },
+                    rfe.cmd_executed(&msg);
+                }
                 _ => {
                     info!("example got msg {:?}", msg);
                 }
@@ -60,6 +63,7 @@ impl App for Example {
 
     fn hk(&mut self, rfe: &mut rfe::Rfe) {
         self.data.hk.counter = self.data.out_data.counter;
+        self.data.hk.cmds = rfe.get_cmd_counters();
         rfe.send(Msg::ExampleHk(self.data.hk));
     }
 
@@ -29,20 +29,22 @@ pub struct HsConfig {
     pub watchdog_timeout: i32,
 }
 
-pub struct Hs<'a> {
+/// Generic over the grabber and watchdog so Hs is Send, and can run in a thread group,
+/// when they are
+pub struct Hs<
+    'a,
+    G: SystemInfoGrabber + ?Sized = dyn SystemInfoGrabber,
+    W: Watchdog + ?Sized = dyn Watchdog,
+> {
     data: HsData,
     config: HsConfig,
-    grabber: &'a mut dyn SystemInfoGrabber,
-    wd: WatchdogRef<'a>,
+    grabber: &'a mut G,
+    wd: Option<&'a mut W>,
     wd_value: ManualAuto<bool>,
 }
 
-impl<'a> Hs<'a> {
-    pub fn new(
-        config: HsConfig,
-        grabber: &'a mut dyn SystemInfoGrabber,
-        mut watchdog: WatchdogRef<'a>,
-    ) -> Self {
+impl<'a, G: SystemInfoGrabber + ?Sized, W: Watchdog + ?Sized> Hs<'a, G, W> {
+    pub fn new(config: HsConfig, grabber: &'a mut G, mut watchdog: Option<&'a mut W>) -> Self {
         watchdog.set_timeout(config.watchdog_timeout);
         if config.watchdog_enable {
             watchdog.enable();
@@ -63,7 +65,7 @@ impl<'a> Hs<'a> {
     }
 }
 
-impl App for Hs<'_> {
+impl<G: SystemInfoGrabber + ?Sized, W: Watchdog + ?Sized> App for Hs<'_, G, W> {
     fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
         self.reset();
         rfe.subscribe(TargetMsg::new(rfe.get_instance(), MsgKind::HsCmd));
@@ -75,9 +77,8 @@ impl App for Hs<'_> {
         self.data.hk.perf.enter(rfe);
         self.data.out_data.counter += 1;
         while let Some(msg) = rfe.recv() {
-            match msg.msg {
+            match &msg.msg {
                 Msg::HsCmd(cmd) => {
-                    self.data.hk.cmd_counter += 1;
                     match cmd {
                         HsCmd::Noop => {
                             info!("Noop command received");
@@ -85,11 +86,13 @@ impl App for Hs<'_> {
                         HsCmd::Reset => {
                             info!("Reset command received");
                             self.data = Default::default();
+                            rfe.reset_cmd_counters();
                         }
-                        HsCmd::WatchdogEnableManual(v) => self.wd_value.manual_set(v),
-                        HsCmd::WatchdogEnableAuto(v) => self.wd_value.auto_set(v),
+                        HsCmd::WatchdogEnableManual(v) => self.wd_value.manual_set(*v),
+                        HsCmd::WatchdogEnableAuto(v) => self.wd_value.auto_set(*v),
                         HsCmd::WatchdogResumeAuto => self.wd_value.resume_auto(),
                     }
+                    rfe.cmd_executed(&msg);
                 }
                 _ => {
                     warn!(
@@ -128,6 +131,7 @@ impl App for Hs<'_> {
 
     fn hk(&mut self, rfe: &mut Rfe) {
         self.data.hk.counter = self.data.out_data.counter;
+        self.data.hk.cmds = rfe.get_cmd_counters();
 
         rfe.send(Msg::HsHk(self.data.hk.clone()));
     }
@@ -57,7 +57,7 @@ pub use watchdog_nix::*;
 
 pub type WatchdogRef<'a> = Option<&'a mut dyn Watchdog>;
 
-impl<'a> Watchdog for WatchdogRef<'a> {
+impl<W: Watchdog + ?Sized> Watchdog for Option<&mut W> {
     fn enable(&mut self) {
         if let Some(wd) = self {
             wd.enable();
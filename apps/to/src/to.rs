@@ -5,7 +5,10 @@ use anyhow::Result;
 use connector::Connector;
 use hashbrown::HashMap;
 use log::*;
-use msg::{Instance, Msg, MsgKind, TargetMsg, TlmSetId, ToCmd, ToHk, ToOutData, ToTlmSet};
+use msg::{
+    CmdError, CmdResult, Instance, Msg, MsgKind, TargetMsg, TlmSetId, ToCmd, ToHk, ToOutData,
+    ToTlmSet,
+};
 use rfe::*;
 
 #[derive(Debug, Clone, Default)]
@@ -14,14 +17,15 @@ pub struct ToData {
     hk: ToHk,
 }
 
-pub struct To<'a> {
+/// Generic over the connector so To is Send, and can run in a thread group, when it is
+pub struct To<'a, C: Connector + ?Sized = dyn Connector> {
     data: ToData,
-    connector: &'a mut dyn Connector,
+    connector: &'a mut C,
     tlm_sets: HashMap<TlmSetId, ToTlmSet>,
 }
 
-impl<'a> To<'a> {
-    pub fn new(connector: &'a mut dyn Connector, tlm_sets: HashMap<TlmSetId, ToTlmSet>) -> Self {
+impl<'a, C: Connector + ?Sized> To<'a, C> {
+    pub fn new(connector: &'a mut C, tlm_sets: HashMap<TlmSetId, ToTlmSet>) -> Self {
         Self {
             connector,
             data: Default::default(),
@@ -41,17 +45,20 @@ impl<'a> To<'a> {
         );
     }
 
-    pub fn handle_cmd(&mut self, rfe: &mut Rfe, cmd: &ToCmd) {
+    pub fn handle_cmd(&mut self, rfe: &mut Rfe, cmd: &ToCmd) -> CmdResult {
+        info!("got cmd {:?}", cmd);
         match cmd {
             ToCmd::Noop => info!("received Noop"),
             ToCmd::Reset => {
                 info!("received Reset");
                 self.data = Default::default();
+                rfe.reset_cmd_counters();
             }
             ToCmd::AddTlmSet(to_tlm_set) => {
                 info!("received AddTlmSet");
                 if let Err(e) = self.tlm_sets.try_insert(to_tlm_set.id, to_tlm_set.clone()) {
                     error!("Could not add tlm set {} {e}", to_tlm_set.id);
+                    return Err(CmdError::AlreadyExists);
                 } else {
                     info!("TlmSet {} added", to_tlm_set.id);
                     self.update_subscriptions(rfe);
@@ -64,6 +71,7 @@ impl<'a> To<'a> {
                     self.update_subscriptions(rfe);
                 } else {
                     warn!("Cannot remove tlm set {}, does not exist", set_id);
+                    return Err(CmdError::NotFound);
                 }
             }
             ToCmd::DisableTlmSet(set_id) => {
@@ -74,6 +82,7 @@ impl<'a> To<'a> {
                     self.update_subscriptions(rfe);
                 } else {
                     warn!("could not disable set {set_id}, does not exist");
+                    return Err(CmdError::NotFound);
                 }
             }
             ToCmd::EnablTlmSet(set_id) => {
@@ -84,14 +93,15 @@ impl<'a> To<'a> {
                     self.update_subscriptions(rfe);
                 } else {
                     warn!("could not enable set {set_id}, does not exist");
+                    return Err(CmdError::NotFound);
                 }
             }
         }
-        info!("got cmd {:?}", cmd);
+        Ok(())
     }
 }
 
-impl App for To<'_> {
+impl<C: Connector + ?Sized> App for To<'_, C> {
     fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
         rfe.subscribe(TargetMsg::new(rfe.get_instance(), MsgKind::ToCmd));
         self.update_subscriptions(rfe);
@@ -107,7 +117,8 @@ impl App for To<'_> {
             if let Msg::ToCmd(cmd) = &msg.msg {
                 if msg.instance == rfe.get_instance() {
                     is_cmd = true;
-                    self.handle_cmd(rfe, cmd);
+                    let result = self.handle_cmd(rfe, cmd);
+                    rfe.cmd_result(&msg, result);
                 }
             }
             if !is_cmd {
@@ -128,7 +139,7 @@ impl App for To<'_> {
             }
         }
         if msgs.len() > 0 {
-            self.connector.send(msgs);
+            self.connector.send(&msgs);
         }
 
         while let Some(msgs) = self.connector.recv() {
@@ -140,6 +151,7 @@ impl App for To<'_> {
     }
 
     fn hk(&mut self, rfe: &mut Rfe) {
+        self.data.hk.cmds = rfe.get_cmd_counters();
         rfe.send(Msg::ToHk(self.data.hk));
     }
 
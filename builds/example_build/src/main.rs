@@ -1,17 +1,29 @@
 use anyhow::Result;
 use connector::UdpConnector;
 use ds::*;
+use event::{EventLogger, EventService};
 use example::*;
+use executor::{Executor, ThreadGroup};
 use hashbrown::HashMap;
 use hs::*;
-use msg::{DsTlmSet, Instance, MsgKind, TargetMsg, TlmSetItem, ToTlmSet};
+use log::LevelFilter;
+use msg::{DsFlushPolicy, DsTlmSet, Instance, MsgKind, TargetMsg, TlmSetItem, ToTlmSet};
 use rfe::*;
 use simple_logger::SimpleLogger;
 use time::UnixTimeDriver;
 use to::*;
 
+static EVENT_LOGGER: EventLogger = EventLogger::new(128);
+
 fn main() -> Result<()> {
-    SimpleLogger::new().init().unwrap();
+    EVENT_LOGGER.set_next(Box::leak(Box::new(SimpleLogger::new())));
+    EVENT_LOGGER.init(LevelFilter::Trace)?;
+    // roll over to a new file every 10 MB or hour
+    let file_settings = DsFileSettings {
+        max_size: 10_000_000,
+        max_age: 3600,
+        enabled: true,
+    };
     let mut record = HashMap::new();
     record.insert(
         0,
@@ -31,6 +43,10 @@ fn main() -> Result<()> {
                 },
             ],
             id: 0,
+            file_settings,
+            flush_policy: DsFlushPolicy::Seconds(1),
+            quota: 0,
+            low_priority: true,
         },
     );
     record.insert(
@@ -51,6 +67,10 @@ fn main() -> Result<()> {
                 },
             ],
             id: 1,
+            file_settings,
+            flush_policy: DsFlushPolicy::Seconds(1),
+            quota: 0,
+            low_priority: false,
         },
     );
     record.insert(
@@ -71,6 +91,10 @@ fn main() -> Result<()> {
                 },
             ],
             id: 2,
+            file_settings,
+            flush_policy: DsFlushPolicy::Seconds(1),
+            quota: 0,
+            low_priority: false,
         },
     );
     record.insert(
@@ -91,11 +115,40 @@ fn main() -> Result<()> {
                 },
             ],
             id: 3,
+            file_settings,
+            flush_policy: DsFlushPolicy::Seconds(1),
+            quota: 0,
+            low_priority: false,
+        },
+    );
+    record.insert(
+        4,
+        DsTlmSet {
+            enabled: true,
+            path: "log/events".to_string(),
+            items: vec![TlmSetItem {
+                counter: 0,
+                target: TargetMsg::new(Instance::All, MsgKind::Event),
+                decimation: 0,
+            }],
+            id: 4,
+            file_settings,
+            flush_policy: DsFlushPolicy::EveryPacket,
+            quota: 0,
+            low_priority: false,
         },
     );
 
     let mut example = Example::new();
-    let mut ds = Ds::<StdDsFile>::new(record, false);
+    let mut events = EventService::new(&EVENT_LOGGER);
+    let mut ds = Ds::<StdDsFile>::new_with_quota(
+        record,
+        false,
+        DsQuota {
+            max_total_size: 1024 * 1024 * 1024,
+            min_free_space: 100 * 1024 * 1024,
+        },
+    );
     // let mut wd = LinuxWatchdog::new().unwrap();
     let mut grabber = StdSystemInfoGrabber::new();
     let mut hs = Hs::new(
@@ -109,7 +162,7 @@ fn main() -> Result<()> {
         },
         &mut grabber,
         // Some(&mut wd),
-        None,
+        None::<&mut (dyn Watchdog + Send)>,
     );
     let mut tcp = UdpConnector::new("127.0.0.1", 7412, "127.0.0.1", 7413)?;
     let mut ground_connector = UdpConnector::new("127.0.0.1", 7010, "127.0.0.1", 7011)?;
@@ -126,15 +179,33 @@ fn main() -> Result<()> {
             enabled: true,
         },
     );
+    dl_sets.insert(
+        1,
+        ToTlmSet {
+            items: vec![TlmSetItem {
+                counter: 0,
+                decimation: 0,
+                target: TargetMsg::new(Instance::All, MsgKind::Event),
+            }],
+            id: 1,
+            enabled: true,
+        },
+    );
     let mut to = To::new(&mut ground_connector, dl_sets);
     let time_driver = UnixTimeDriver::new();
-    let mut instance = RfeInstance::new(Instance::Example, &time_driver);
-    instance.add_app("example", &mut example)?;
-    instance.add_app("to", &mut to)?;
-    instance.add_app("DS", &mut ds)?;
-    instance.add_app("HS", &mut hs)?;
-    instance.add_connector(&mut tcp);
+    let mut main_group = ThreadGroup::new("main");
+    main_group.add_app("example", &mut example);
+    main_group.add_app("to", &mut to);
+    main_group.add_app("DS", &mut ds);
+    main_group.add_app("events", &mut events);
+    main_group.add_connector(&mut tcp);
+    // filesystem and temperature checks are slow, keep them from delaying the other apps
+    let mut hs_group = ThreadGroup::new_with_tick_rate("hs", 10);
+    hs_group.add_app("HS", &mut hs);
 
-    instance.start();
+    let mut executor = Executor::new(Instance::Example, &time_driver);
+    executor.add_group(main_group)?;
+    executor.add_group(hs_group)?;
+    executor.start()?;
     return Ok(());
 }
@@ -9,9 +9,11 @@ rp2040_timer_monotonic!(Mono);
 mod app {
     use super::*;
 
+    use ds::{Ds, DsFlashRegions, FlashDsFile};
     use embassy_rp::{
+        flash::{Blocking, Flash},
         interrupt::typelevel,
-        peripherals::USB,
+        peripherals::{FLASH, USB},
         usb::{self, Driver},
     };
     use embedded_hal::digital::v2::OutputPin;
@@ -19,7 +21,10 @@ mod app {
     use fugit::Duration;
     use hs::{Hs, HsConfig, Rp2040Watchdog, StubSystemInfoGrabber};
     use log::info;
-    use msg::{Instance, MsgKind, MsgPacket, TargetMsg, TlmSetItem, ToTlmSet};
+    use msg::{
+        DsFileSettings, DsFlushPolicy, DsTlmSet, Instance, MsgKind, MsgPacket, TargetMsg,
+        TlmSetItem, ToTlmSet,
+    };
     use rfe::{connector::Connector, Rate, *};
     use rp_pico::hal::{
         clocks,
@@ -28,12 +33,14 @@ mod app {
     };
 
     use anyhow::Result;
-    use core::{mem::MaybeUninit, ptr::addr_of_mut};
+    use core::{cell::RefCell, mem::MaybeUninit, ops::Range, ptr::addr_of_mut};
+    use critical_section::Mutex;
     use embedded_alloc::LlffHeap as Heap;
     use panic_halt as _;
     use rp_pico::XOSC_CRYSTAL_FREQ;
     use time::Rp2040TimeDriver;
     extern crate alloc;
+    use alloc::rc::Rc;
     use alloc::vec;
     use alloc::vec::Vec;
     use hashbrown::HashMap;
@@ -42,12 +49,39 @@ mod app {
     #[global_allocator]
     static HEAP: Heap = Heap::empty();
 
+    const FLASH_SIZE: usize = 2 * 1024 * 1024;
+    type PicoFlash = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+
+    /// set in init, used by Ds through PicoFlashRegions
+    static DS_FLASH: Mutex<RefCell<Option<PicoFlash>>> = Mutex::new(RefCell::new(None));
+
+    /// Ds logs in the last 64 KiB of flash, well after the program
+    struct PicoFlashRegions;
+
+    impl DsFlashRegions for PicoFlashRegions {
+        type Flash = PicoFlash;
+
+        fn with_region<T>(
+            path: &str,
+            f: impl FnOnce(&mut PicoFlash, Range<u32>) -> T,
+        ) -> Option<T> {
+            let range = match path {
+                "log/example" => (FLASH_SIZE - 64 * 1024) as u32..FLASH_SIZE as u32,
+                _ => return None,
+            };
+            critical_section::with(|cs| {
+                let mut flash = DS_FLASH.borrow_ref_mut(cs);
+                Some(f(flash.as_mut()?, range))
+            })
+        }
+    }
+
     /// will log messages to log
     #[derive(Debug)]
     struct LogConnector;
 
     impl Connector for LogConnector {
-        fn send(&mut self, msgs: Vec<MsgPacket>) {
+        fn send(&mut self, msgs: &[Rc<MsgPacket>]) {
             info!("got msgs: {:?}", msgs);
         }
         fn recv(&mut self) -> Option<Vec<MsgPacket>> {
@@ -129,6 +163,11 @@ mod app {
         }
 
         let driver = Driver::new(p.USB, UsbBinding {});
+        critical_section::with(|cs| {
+            DS_FLASH
+                .borrow_ref_mut(cs)
+                .replace(Flash::new_blocking(p.FLASH));
+        });
         let sio = Sio::new(ctx.device.SIO);
         let gpioa = rp_pico::Pins::new(
             ctx.device.IO_BANK0,
@@ -193,6 +232,30 @@ mod app {
             },
         );
         let mut to = To::new(&mut log_connector, tlmsets);
+        let mut ds_tlm_sets = HashMap::new();
+        ds_tlm_sets.insert(
+            0,
+            DsTlmSet {
+                items: vec![TlmSetItem {
+                    target: TargetMsg::new(Instance::All, MsgKind::ExampleHk),
+                    counter: 0,
+                    decimation: 0,
+                }],
+                id: 0,
+                enabled: true,
+                path: "log/example".into(),
+                file_settings: DsFileSettings {
+                    max_size: 4096,
+                    max_age: 0,
+                    enabled: true,
+                },
+                flush_policy: DsFlushPolicy::Bytes(256),
+                quota: 0,
+                low_priority: false,
+            },
+        );
+        // the log wraps over its oldest files, no quota is needed
+        let mut ds = Ds::<FlashDsFile<PicoFlashRegions>>::new(ds_tlm_sets, true);
         let mut example = Example::new();
         let mut wd = Rp2040Watchdog::new(ctx.local.wd.take().unwrap());
 
@@ -216,13 +279,19 @@ mod app {
         instance.add_app("to", &mut to).unwrap();
         instance.add_app("hs", &mut hs).unwrap();
         instance.add_app("example", &mut example).unwrap();
+        instance.add_app("ds", &mut ds).unwrap();
+        // keep queued msgs well within the 16 KiB heap
+        for name in ["blink_app", "to", "hs", "example", "ds"] {
+            instance.set_queue_depth(name, 16).unwrap();
+        }
 
-        let mut next_time = Mono::now() + Duration::<u64, 1, 1000000>::from_ticks(10000);
+        let mut next_time =
+            Mono::now() + Duration::<u64, 1, 1000000>::from_ticks(instance.get_tick_period());
 
         loop {
-            instance.run();
+            instance.run_async().await;
             Mono::delay_until(next_time).await;
-            next_time += Duration::<u64, 1, 1000000>::from_ticks(10000);
+            next_time += Duration::<u64, 1, 1000000>::from_ticks(instance.get_tick_period());
         }
     }
 }
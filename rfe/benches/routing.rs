@@ -0,0 +1,145 @@
+//! Compares RfeInstance routing against probing every app's subscriptions for every msg,
+//! which is how msgs were routed before the routing table.
+//!
+//! cargo bench -p rfe --bench routing
+
+use std::{collections::VecDeque, hint::black_box, time::Instant};
+
+use anyhow::Result;
+use hashbrown::HashSet;
+use rfe::{msg::*, time::SchTimeDriver, App, Rate, Rfe, RfeInstance};
+
+const APPS: usize = 32;
+const MSGS_PER_TICK: usize = 64;
+const TICKS: u32 = 2000;
+
+const KINDS: [MsgKind; 8] = [
+    MsgKind::ExampleHk,
+    MsgKind::ExampleOutData,
+    MsgKind::DsHk,
+    MsgKind::DsOutData,
+    MsgKind::HsHk,
+    MsgKind::HsOutData,
+    MsgKind::ToHk,
+    MsgKind::ToOutData,
+];
+
+fn msg(i: usize) -> Msg {
+    KINDS[i % KINDS.len()].to_default()
+}
+
+/// each app subscribes to 2 of the kinds
+fn subs(app: usize) -> Vec<TargetMsg> {
+    vec![
+        TargetMsg::new(Instance::Example, KINDS[app % KINDS.len()]),
+        TargetMsg::new(Instance::All, KINDS[(app + 3) % KINDS.len()]),
+    ]
+}
+
+struct BenchApp {
+    index: usize,
+    subscribe: bool,
+}
+
+impl App for BenchApp {
+    fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
+        if self.subscribe {
+            rfe.subscribe_all(subs(self.index));
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        if self.index == 0 {
+            for i in 0..MSGS_PER_TICK {
+                rfe.send(msg(i));
+            }
+        }
+        while let Some(msg) = rfe.recv() {
+            black_box(msg);
+        }
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+/// time per RfeInstance::run, without subscribers it measures everything but routing
+fn bench_instance(subscribe: bool) -> f64 {
+    let time_driver = SchTimeDriver::new();
+    let mut apps = (0..APPS)
+        .map(|index| BenchApp { index, subscribe })
+        .collect::<Vec<_>>();
+    let names = (0..APPS).map(|i| format!("app{i}")).collect::<Vec<_>>();
+    let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+    for (name, app) in names.iter().zip(apps.iter_mut()) {
+        instance.add_app(name, app).unwrap();
+    }
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        instance.run();
+    }
+    start.elapsed().as_secs_f64() / TICKS as f64
+}
+
+fn bench_probing() -> f64 {
+    let mut apps = (0..APPS)
+        .map(|i| {
+            (
+                subs(i).into_iter().collect::<HashSet<_>>(),
+                VecDeque::<MsgPacket>::new(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        let msgs = (0..MSGS_PER_TICK)
+            .map(|i| MsgPacket::new(Instance::Example, msg(i), 0))
+            .collect::<Vec<_>>();
+        for (subscriptions, queue) in &mut apps {
+            for msg in &msgs {
+                if subscriptions.contains(&TargetMsg::new(Instance::Example, msg.msg.kind()))
+                    || subscriptions.contains(&TargetMsg::new(Instance::All, msg.msg.kind()))
+                {
+                    queue.push_back(msg.clone());
+                }
+            }
+        }
+        for (_, queue) in &mut apps {
+            while let Some(msg) = queue.pop_front() {
+                black_box(msg);
+            }
+        }
+    }
+    start.elapsed().as_secs_f64() / TICKS as f64
+}
+
+fn main() {
+    let full = bench_instance(true);
+    let overhead = bench_instance(false);
+    let probing = bench_probing();
+    println!("{APPS} apps, {MSGS_PER_TICK} msgs per tick, {TICKS} ticks");
+    println!(
+        "RfeInstance::run:                  {:8.2} us/tick",
+        full * 1e6
+    );
+    println!(
+        "RfeInstance::run, no subscribers:  {:8.2} us/tick",
+        overhead * 1e6
+    );
+    println!(
+        "routing table:                     {:8.2} us/tick",
+        (full - overhead) * 1e6
+    );
+    println!(
+        "per msg probing:                   {:8.2} us/tick",
+        probing * 1e6
+    );
+}
@@ -0,0 +1,117 @@
+use quote::ToTokens;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+use syn::{Attribute, Fields, Item, Visibility};
+
+fn find_sources(dir: &Path, paths: &mut Vec<PathBuf>) {
+    for ent in fs::read_dir(dir).expect("failed to read src") {
+        let path = ent.expect("failed to read src").path();
+        if path.is_dir() {
+            find_sources(&path, paths);
+        } else if path.extension().is_some_and(|x| x == "rs") {
+            paths.push(path);
+        }
+    }
+}
+
+/// True if the item derives Encode, directly or through cfg_attr
+fn derives_encode(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|x| x.path().is_ident("derive") || x.path().is_ident("cfg_attr"))
+        .any(|x| {
+            x.meta
+                .to_token_stream()
+                .to_string()
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .any(|x| x == "Encode")
+        })
+}
+
+/// Tokens printed with uniform spacing, so formatting doesn't change them
+fn tokens<T: ToTokens>(value: &T) -> String {
+    value.to_token_stream().to_string()
+}
+
+fn fields(fields: &Fields) -> String {
+    let types = fields.iter().map(|x| match &x.ident {
+        Some(name) => format!("{name}: {}", tokens(&x.ty)),
+        None => tokens(&x.ty),
+    });
+    let types = types.collect::<Vec<_>>().join(", ");
+    match fields {
+        Fields::Named(_) => format!(" {{ {types} }}"),
+        Fields::Unnamed(_) => format!("({types})"),
+        Fields::Unit => String::new(),
+    }
+}
+
+/// The types sent on the wire or written to logs: the items deriving Encode and public
+/// type aliases such as Timestamp, reduced to their names, fields and variants
+fn get_wire_types(items: &[Item], types: &mut Vec<String>) {
+    for item in items {
+        match item {
+            Item::Struct(x) if derives_encode(&x.attrs) => types.push(format!(
+                "struct {}{}{}",
+                x.ident,
+                tokens(&x.generics),
+                fields(&x.fields)
+            )),
+            Item::Enum(x) if derives_encode(&x.attrs) => {
+                let variants = x.variants.iter().map(|v| match &v.discriminant {
+                    Some((_, value)) => {
+                        format!("{}{} = {}", v.ident, fields(&v.fields), tokens(value))
+                    }
+                    None => format!("{}{}", v.ident, fields(&v.fields)),
+                });
+                types.push(format!(
+                    "enum {}{} {{ {} }}",
+                    x.ident,
+                    tokens(&x.generics),
+                    variants.collect::<Vec<_>>().join(", ")
+                ));
+            }
+            Item::Type(x) if matches!(x.vis, Visibility::Public(_)) => types.push(format!(
+                "type {}{} = {}",
+                x.ident,
+                tokens(&x.generics),
+                tokens(&x.ty)
+            )),
+            Item::Mod(x) => {
+                if let Some((_, items)) = &x.content {
+                    get_wire_types(items, types);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Hashes the wire types so logs can be matched to the schema that wrote them
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    let mut paths = Vec::new();
+    find_sources(Path::new("src"), &mut paths);
+
+    let mut types = Vec::new();
+    for path in paths {
+        let source = fs::read_to_string(&path).expect("failed to read source");
+        let file = syn::parse_file(&source)
+            .unwrap_or_else(|e| panic!("failed to parse {}: {e}", path.display()));
+        get_wire_types(&file.items, &mut types);
+    }
+    // moving a type between files doesn't change the schema
+    types.sort();
+
+    // FNV-1a
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for b in types.iter().flat_map(|x| x.bytes().chain([b'\n'])) {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("schema_hash.rs");
+    fs::write(out, format!("{hash:#018x}")).expect("failed to write schema hash");
+}
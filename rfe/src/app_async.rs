@@ -0,0 +1,150 @@
+extern crate alloc;
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use alloc::sync::Arc;
+use anyhow::Result;
+use core::{
+    future::Future,
+    pin::{pin, Pin},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{msg::OverrunResponse, App, Rate, Rfe, DEFAULT_QUEUE_DEPTH};
+
+/// Run of an AsyncApp, borrows the app and its Rfe until it completes
+pub type AppFuture<'b> = Pin<Box<dyn Future<Output = ()> + 'b>>;
+
+/// App whose run can await I/O.
+///
+/// RfeInstance::run_async awaits run so the executor can do other work while it's pending,
+/// the apps after it in the tick wait for it to complete as they would for a slow App.
+/// RfeInstance::run blocks on run until it completes, see block_on.
+// futures are only polled by the instance's task, they don't need to be Send
+#[allow(async_fn_in_trait)]
+pub trait AsyncApp {
+    fn init(&mut self, rfe: &mut Rfe) -> Result<()>;
+    async fn run(&mut self, rfe: &mut Rfe);
+    fn hk(&mut self, rfe: &mut Rfe);
+    fn out_data(&mut self, rfe: &mut Rfe);
+    fn get_app_rate(&self) -> Rate;
+
+    fn get_hk_rate(&self) -> Rate {
+        Rate::Hz1
+    }
+
+    fn get_out_data_rate(&self) -> Rate {
+        self.get_app_rate()
+    }
+
+    fn get_queue_depth(&self) -> usize {
+        DEFAULT_QUEUE_DEPTH
+    }
+
+    /// includes the time run is pending
+    fn get_time_budget(&self) -> Option<u32> {
+        None
+    }
+
+    fn get_overrun_response(&self) -> OverrunResponse {
+        OverrunResponse::Log
+    }
+
+    fn shutdown(&mut self, _rfe: &mut Rfe) {}
+}
+
+impl<T: AsyncApp> App for T {
+    fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
+        AsyncApp::init(self, rfe)
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        block_on(AsyncApp::run(self, rfe));
+    }
+
+    fn run_async<'b>(&'b mut self, rfe: &'b mut Rfe) -> Option<AppFuture<'b>> {
+        Some(Box::pin(AsyncApp::run(self, rfe)))
+    }
+
+    fn hk(&mut self, rfe: &mut Rfe) {
+        AsyncApp::hk(self, rfe);
+    }
+
+    fn out_data(&mut self, rfe: &mut Rfe) {
+        AsyncApp::out_data(self, rfe);
+    }
+
+    fn get_app_rate(&self) -> Rate {
+        AsyncApp::get_app_rate(self)
+    }
+
+    fn get_hk_rate(&self) -> Rate {
+        AsyncApp::get_hk_rate(self)
+    }
+
+    fn get_out_data_rate(&self) -> Rate {
+        AsyncApp::get_out_data_rate(self)
+    }
+
+    fn get_queue_depth(&self) -> usize {
+        AsyncApp::get_queue_depth(self)
+    }
+
+    fn get_time_budget(&self) -> Option<u32> {
+        AsyncApp::get_time_budget(self)
+    }
+
+    fn get_overrun_response(&self) -> OverrunResponse {
+        AsyncApp::get_overrun_response(self)
+    }
+
+    fn shutdown(&mut self, rfe: &mut Rfe) {
+        AsyncApp::shutdown(self, rfe);
+    }
+}
+
+/// Polls the future until it completes. With std the thread parks while the future is
+/// pending until its waker is woken, as the Future contract requires of pending futures.
+/// Without std there's no thread to park and it busy waits.
+///
+/// RfeInstance::run goes through here every tick, ticks without a pending AsyncApp complete
+/// on the first poll.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    #[cfg(feature = "std")]
+    return THREAD_WAKER.with(|waker| poll_until_ready(future, waker));
+    #[cfg(not(feature = "std"))]
+    poll_until_ready(future, Waker::noop())
+}
+
+fn poll_until_ready<F: Future>(future: F, waker: &Waker) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        #[cfg(feature = "std")]
+        std::thread::park();
+        #[cfg(not(feature = "std"))]
+        core::hint::spin_loop();
+    }
+}
+
+/// Unparks the thread blocked in block_on
+#[cfg(feature = "std")]
+struct ThreadWaker(std::thread::Thread);
+
+#[cfg(feature = "std")]
+impl alloc::task::Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static THREAD_WAKER: Waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+}
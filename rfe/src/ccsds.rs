@@ -0,0 +1,138 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use bincode::{
+    config::{BigEndian, Configuration, Fixint},
+    decode_from_slice, encode_to_vec,
+};
+use hashbrown::HashMap;
+use log::*;
+
+use crate::msg::{Instance, Msg, MsgKind, MsgPacket};
+
+pub const CCSDS_BINCODE_CONFIG: Configuration<BigEndian, Fixint> = bincode::config::standard()
+    .with_big_endian()
+    .with_fixed_int_encoding();
+
+pub const PRIMARY_HEADER_SIZE: usize = 6;
+/// u64 big endian timestamp in microseconds
+pub const SECONDARY_HEADER_SIZE: usize = 8;
+pub const MAX_DATA_SIZE: usize = 65536;
+
+const KIND_BITS: u16 = 7;
+const KIND_MASK: u16 = (1 << KIND_BITS) - 1;
+const SEQ_COUNT_MASK: u16 = 0x3FFF;
+/// unsegmented user data
+const SEQ_FLAGS: u16 = 0b11 << 14;
+
+pub fn get_apid(instance: Instance, kind: MsgKind) -> u16 {
+    ((instance as u16) << KIND_BITS) | (kind as u16 & KIND_MASK)
+}
+
+pub fn from_apid(apid: u16) -> Option<(Instance, MsgKind)> {
+    // fixed int enums are encoded as their u32 variant index
+    let instance = (apid >> KIND_BITS) as u32;
+    let (instance, _) =
+        decode_from_slice::<Instance, _>(&instance.to_be_bytes(), CCSDS_BINCODE_CONFIG).ok()?;
+    let kind = *MsgKind::to_vec().get((apid & KIND_MASK) as usize)?;
+    Some((instance, kind))
+}
+
+/// Commands are sent as telecommand packets, everything else as telemetry
+fn is_cmd(kind: MsgKind) -> bool {
+    matches!(
+        kind,
+        MsgKind::SetTimeCmd
+            | MsgKind::ReinitApp
+            | MsgKind::SetAppRateCmd
+            | MsgKind::StopApp
+            | MsgKind::StartApp
+            | MsgKind::RemoveApp
+            | MsgKind::SetTimeBudgetCmd
+            | MsgKind::EventFilterCmd
+            | MsgKind::ReliableCmd
+            | MsgKind::ExampleCmd
+            | MsgKind::DsCmd
+            | MsgKind::HsCmd
+            | MsgKind::ToCmd
+    )
+}
+
+/// CCSDS Space Packet Protocol (CCSDS 133.0-B) codec, one space packet per MsgPacket.
+///
+/// The APID holds the instance in the upper 4 bits and the msg kind in the lower 7 bits,
+/// the secondary header holds the timestamp and the user data is the msg encoded with
+/// CCSDS_BINCODE_CONFIG. A sequence count is kept per APID.
+///
+/// Only UDP carries the space packets bare, see Encoding::Ccsds.
+#[derive(Debug, Default)]
+pub struct CcsdsCodec {
+    seq_counts: HashMap<u16, u16>,
+}
+
+impl CcsdsCodec {
+    pub fn new() -> Self {
+        Self {
+            seq_counts: HashMap::new(),
+        }
+    }
+
+    pub fn encode_packet(&mut self, msg: &MsgPacket, buf: &mut Vec<u8>) {
+        let kind = msg.msg.kind();
+        let apid = get_apid(msg.instance, kind);
+        let user_data = encode_to_vec(&msg.msg, CCSDS_BINCODE_CONFIG)
+            .expect("failed to serialize space packet");
+        let data_len = SECONDARY_HEADER_SIZE + user_data.len();
+        if data_len > MAX_DATA_SIZE {
+            warn!("{:?} too large for a space packet, dropping", kind);
+            return;
+        }
+
+        let seq_count = self.seq_counts.entry(apid).or_insert(0);
+        let packet_type = if is_cmd(kind) { 1 << 12 } else { 0 };
+        // version 0, secondary header present
+        let id = packet_type | (1 << 11) | apid;
+        buf.extend_from_slice(&id.to_be_bytes());
+        buf.extend_from_slice(&(SEQ_FLAGS | *seq_count).to_be_bytes());
+        buf.extend_from_slice(&((data_len - 1) as u16).to_be_bytes());
+        buf.extend_from_slice(&msg.timestamp.to_be_bytes());
+        buf.extend_from_slice(&user_data);
+        *seq_count = (*seq_count + 1) & SEQ_COUNT_MASK;
+    }
+
+    pub fn encode(&mut self, msgs: &[MsgPacket]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for msg in msgs {
+            self.encode_packet(msg, &mut buf);
+        }
+        buf
+    }
+
+    /// Decodes consecutive space packets, stops at the first malformed packet
+    pub fn decode(&mut self, mut bytes: &[u8]) -> Vec<MsgPacket> {
+        let mut msgs = Vec::new();
+        while bytes.len() >= PRIMARY_HEADER_SIZE {
+            let id = u16::from_be_bytes([bytes[0], bytes[1]]);
+            let data_len = u16::from_be_bytes([bytes[4], bytes[5]]) as usize + 1;
+            let end = PRIMARY_HEADER_SIZE + data_len;
+            if bytes.len() < end || id & (1 << 11) == 0 || data_len < SECONDARY_HEADER_SIZE {
+                warn!("malformed space packet, dropping {} bytes", bytes.len());
+                break;
+            }
+
+            let apid = id & 0x7FF;
+            let data = &bytes[PRIMARY_HEADER_SIZE..end];
+            let timestamp = u64::from_be_bytes(data[..SECONDARY_HEADER_SIZE].try_into().unwrap());
+            match (
+                from_apid(apid),
+                decode_from_slice::<Msg, _>(&data[SECONDARY_HEADER_SIZE..], CCSDS_BINCODE_CONFIG),
+            ) {
+                (Some((instance, kind)), Ok((msg, _))) if msg.kind() == kind => {
+                    msgs.push(MsgPacket::new(instance, msg, timestamp));
+                }
+                _ => warn!("failed to decode space packet with apid {apid}"),
+            }
+            bytes = &bytes[end..];
+        }
+        msgs
+    }
+}
@@ -2,31 +2,39 @@ use core::fmt::Debug;
 
 use crate::msg::MsgPacket;
 extern crate alloc;
-use alloc::vec::Vec;
+use alloc::{rc::Rc, vec::Vec};
 
 pub trait Connector: Debug {
-    fn send(&mut self, msgs: Vec<MsgPacket>);
+    /// msgs are shared with the local subscribers, connectors encode them without a copy
+    fn send(&mut self, msgs: &[Rc<MsgPacket>]);
     fn recv(&mut self) -> Option<Vec<MsgPacket>>;
+
+    /// Called before the instance exits, should try to send anything still buffered
+    fn flush(&mut self) {}
 }
 
 #[cfg(feature = "std")]
 mod connector_std {
     extern crate alloc;
     extern crate std;
-    use alloc::vec::Vec;
+    use alloc::{rc::Rc, vec::Vec};
     use anyhow::{anyhow, Result};
-    use bincode::{decode_from_slice, encode_to_vec};
     use core::time::Duration;
     use log::*;
     use mio::net::{TcpListener, TcpStream, UdpSocket};
     use std::{
-        io::{Read, Write},
+        io::{ErrorKind, Read, Write},
         net::ToSocketAddrs,
         sync::mpsc::{self, Receiver, Sender},
+        thread::sleep,
+        time::Instant,
     };
 
     use super::Connector;
-    use crate::{msg::MsgPacket, BINCODE_CONFIG};
+    use crate::{
+        framing::{encode_msgs, Encoding, FrameDecoder},
+        msg::MsgPacket,
+    };
 
     #[derive(Debug)]
     pub struct MemConnector {
@@ -52,8 +60,11 @@ mod connector_std {
     }
 
     impl Connector for MemConnector {
-        fn send(&mut self, msgs: Vec<MsgPacket>) {
-            self.sender.send(msgs).ok();
+        fn send(&mut self, msgs: &[Rc<MsgPacket>]) {
+            // msgs crossing threads can't be shared
+            self.sender
+                .send(msgs.iter().map(|x| (**x).clone()).collect())
+                .ok();
         }
 
         fn recv(&mut self) -> Option<Vec<MsgPacket>> {
@@ -68,6 +79,24 @@ mod connector_std {
         client: TcpStream,
         remote_addr: &'static str,
         remote_port: u16,
+        decoder: FrameDecoder,
+        /// framed bytes not yet accepted by the socket
+        send_buf: Vec<u8>,
+        encoding: Encoding,
+    }
+
+    /// Frames waiting on a slow or disconnected remote are dropped past this size
+    const MAX_SEND_BUF: usize = 1024 * 1024;
+    /// How long flush waits on the socket before giving up
+    const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+    fn tcp_connect(addr: &str, port: u16) -> Result<TcpStream> {
+        Ok(TcpStream::connect(
+            (addr, port)
+                .to_socket_addrs()?
+                .next()
+                .ok_or(anyhow!("failed to parse ip address"))?,
+        )?)
     }
 
     impl TcpConnector {
@@ -84,34 +113,53 @@ mod connector_std {
                         .next()
                         .ok_or(anyhow!("failed to parse ip address"))?,
                 )?,
-                client: TcpStream::connect(
-                    (remote_addr, remote_port)
-                        .to_socket_addrs()?
-                        .next()
-                        .ok_or(anyhow!("failed to parse ip address"))?,
-                )?,
+                client: tcp_connect(remote_addr, remote_port)?,
                 listen: None,
                 remote_addr,
                 remote_port,
+                decoder: FrameDecoder::new(),
+                send_buf: Vec::new(),
+                encoding: Encoding::Bincode,
             })
         }
+
+        pub fn set_encoding(&mut self, encoding: Encoding) {
+            self.encoding = encoding;
+        }
+
+        fn flush_send_buf(&mut self) {
+            while !self.send_buf.is_empty() {
+                match self.client.write(&self.send_buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        self.send_buf.drain(..n);
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        warn!("tcp write error {e}");
+                        // a partially written frame is skipped by the remote's resync
+                        self.send_buf.clear();
+                        match tcp_connect(self.remote_addr, self.remote_port) {
+                            Ok(client) => self.client = client,
+                            Err(e) => error!("tcp reconnect error {e}"),
+                        }
+                        break;
+                    }
+                }
+            }
+        }
     }
 
     impl Connector for TcpConnector {
-        fn send(&mut self, msgs: Vec<MsgPacket>) {
-            let r = encode_to_vec(&msgs, BINCODE_CONFIG).expect("failed to serialize tcp packet");
-            if let Err(e) = self.client.write(&r) {
-                warn!("tcp write error {e}");
-                self.client = TcpStream::connect(
-                    (self.remote_addr, self.remote_port)
-                        .to_socket_addrs()
-                        .unwrap()
-                        .next()
-                        .ok_or(anyhow!("failed to parse ip address"))
-                        .unwrap(),
-                )
-                .unwrap();
+        fn send(&mut self, msgs: &[Rc<MsgPacket>]) {
+            if self.send_buf.len() > MAX_SEND_BUF {
+                warn!("tcp send buffer full, dropping {} msgs", msgs.len());
+            } else {
+                if let Err(e) = encode_msgs(msgs, &mut self.encoding, &mut self.send_buf) {
+                    error!("tcp send {e}");
+                }
             }
+            self.flush_send_buf();
         }
 
         fn recv(&mut self) -> Option<Vec<MsgPacket>> {
@@ -119,24 +167,50 @@ mod connector_std {
             if let Ok((stream, sa)) = self.listener.accept() {
                 info!("new connection from {}", sa);
                 self.listen = Some(stream);
+                self.decoder = FrameDecoder::new();
             }
             if let Some(l) = &mut self.listen {
-                if let Ok(a) = l.read(&mut read_buf) {
-                    if let Ok((r, _)) =
-                        decode_from_slice::<Vec<MsgPacket>, _>(&read_buf[0..a], BINCODE_CONFIG)
-                    {
-                        return Some(r);
+                loop {
+                    match l.read(&mut read_buf) {
+                        Ok(0) => {
+                            info!("connection closed");
+                            self.listen = None;
+                            break;
+                        }
+                        Ok(a) => self.decoder.push(&read_buf[0..a]),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => {
+                            warn!("tcp read error {e}");
+                            self.listen = None;
+                            break;
+                        }
                     }
                 }
             }
 
-            return None;
+            self.decoder.next_msgs(&mut self.encoding)
+        }
+
+        fn flush(&mut self) {
+            let deadline = Instant::now() + FLUSH_TIMEOUT;
+            self.flush_send_buf();
+            while !self.send_buf.is_empty() && Instant::now() < deadline {
+                sleep(Duration::from_millis(1));
+                self.flush_send_buf();
+            }
+            if !self.send_buf.is_empty() {
+                warn!(
+                    "tcp flush timed out, {} bytes not sent",
+                    self.send_buf.len()
+                );
+            }
         }
     }
 
     #[derive(Debug)]
     pub struct UdpConnector {
         socket: UdpSocket,
+        encoding: Encoding,
     }
 
     impl UdpConnector {
@@ -158,24 +232,27 @@ mod connector_std {
                     .next()
                     .ok_or(anyhow!("failed to parse ip address"))?,
             )?;
-            Ok(Self { socket })
+            Ok(Self {
+                socket,
+                encoding: Encoding::Bincode,
+            })
+        }
+
+        pub fn set_encoding(&mut self, encoding: Encoding) {
+            self.encoding = encoding;
         }
     }
 
     impl Connector for UdpConnector {
-        fn send(&mut self, msgs: Vec<MsgPacket>) {
-            let r = encode_to_vec(&msgs, BINCODE_CONFIG).expect("failed to serialize udp packet");
+        fn send(&mut self, msgs: &[Rc<MsgPacket>]) {
+            let r = self.encoding.encode(msgs);
             self.socket.send(&r).ok();
         }
 
         fn recv(&mut self) -> Option<Vec<MsgPacket>> {
             let mut read_buf = [0_u8; 4096];
             if let Ok(a) = self.socket.recv(&mut read_buf) {
-                if let Ok((r, _)) =
-                    decode_from_slice::<Vec<MsgPacket>, _>(&read_buf[0..a], BINCODE_CONFIG)
-                {
-                    return Some(r);
-                }
+                return self.encoding.decode(&read_buf[0..a]);
             }
 
             return None;
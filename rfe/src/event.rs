@@ -0,0 +1,304 @@
+extern crate alloc;
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::{String, ToString},
+};
+#[cfg(target_has_atomic = "ptr")]
+use anyhow::anyhow;
+use anyhow::Result;
+use core::cell::RefCell;
+use critical_section::Mutex;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{
+    msg::{CmdResult, Event, EventFilter, EventFilterCmd, EventSeverity, Msg, MsgKind, TargetMsg},
+    time::Timestamp,
+    App, Rate, Rfe,
+};
+
+/// Events sent per source each throttle window, later ones are counted and dropped
+pub const DEFAULT_MAX_EVENTS: u32 = 10;
+/// microseconds
+pub const DEFAULT_THROTTLE_WINDOW: Timestamp = 1_000_000;
+
+#[derive(Debug)]
+struct QueuedEvent {
+    severity: EventSeverity,
+    source: String,
+    text: String,
+}
+
+struct LoggerState {
+    queue: VecDeque<QueuedEvent>,
+    filters: BTreeMap<String, LevelFilter>,
+    default_filter: LevelFilter,
+    /// records dropped because the queue was full
+    dropped: u32,
+    next: Option<&'static dyn Log>,
+}
+
+impl LoggerState {
+    fn get_filter(&self, source: &str) -> LevelFilter {
+        self.filters
+            .get(source)
+            .copied()
+            .unwrap_or(self.default_filter)
+    }
+}
+
+/// log::Log implementation queueing records for an EventService to send as Event msgs.
+///
+/// The source of an event is the first segment of the record's target, the crate name
+/// unless the target is set in the log macro. The logger doesn't know which app is running,
+/// so apps built from the same crate share a source, its filter and its throttling. An app
+/// that needs its own source logs with `target: "<app name>"`.
+/// Records are also passed to the logger set with set_next, such as a console logger.
+pub struct EventLogger {
+    capacity: usize,
+    state: Mutex<RefCell<LoggerState>>,
+}
+
+fn get_source(target: &str) -> &str {
+    target.split("::").next().unwrap_or(target)
+}
+
+impl EventLogger {
+    /// capacity is how many records are queued between EventService runs
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(RefCell::new(LoggerState {
+                queue: VecDeque::new(),
+                filters: BTreeMap::new(),
+                default_filter: LevelFilter::Info,
+                dropped: 0,
+                next: None,
+            })),
+        }
+    }
+
+    /// Sets the logger as the global logger
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn init(&'static self, max_level: LevelFilter) -> Result<()> {
+        log::set_logger(self).map_err(|e| anyhow!("failed to set logger {e}"))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+
+    pub fn set_next(&self, logger: &'static dyn Log) {
+        critical_section::with(|cs| self.state.borrow_ref_mut(cs).next = Some(logger));
+    }
+
+    /// An empty source sets the filter of sources without their own
+    pub fn set_filter(&self, source: &str, filter: LevelFilter) {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            if source.is_empty() {
+                state.default_filter = filter;
+            } else {
+                state.filters.insert(source.to_string(), filter);
+            }
+        });
+    }
+
+    fn take(&self) -> (VecDeque<QueuedEvent>, u32) {
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            let dropped = core::mem::take(&mut state.dropped);
+            (core::mem::take(&mut state.queue), dropped)
+        })
+    }
+}
+
+impl Log for EventLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        critical_section::with(|cs| {
+            metadata.level()
+                <= self
+                    .state
+                    .borrow_ref(cs)
+                    .get_filter(get_source(metadata.target()))
+        })
+    }
+
+    fn log(&self, record: &Record) {
+        let next = critical_section::with(|cs| self.state.borrow_ref(cs).next);
+        if let Some(next) = next {
+            next.log(record);
+        }
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let event = QueuedEvent {
+            severity: match record.level() {
+                Level::Error => EventSeverity::Error,
+                Level::Warn => EventSeverity::Warn,
+                Level::Info => EventSeverity::Info,
+                Level::Debug | Level::Trace => EventSeverity::Debug,
+            },
+            source: get_source(record.target()).to_string(),
+            text: format!("{}", record.args()),
+        };
+        critical_section::with(|cs| {
+            let mut state = self.state.borrow_ref_mut(cs);
+            if state.queue.len() < self.capacity {
+                state.queue.push_back(event);
+            } else {
+                state.dropped += 1;
+            }
+        });
+    }
+
+    fn flush(&self) {
+        let next = critical_section::with(|cs| self.state.borrow_ref(cs).next);
+        if let Some(next) = next {
+            next.flush();
+        }
+    }
+}
+
+impl From<EventFilter> for LevelFilter {
+    fn from(value: EventFilter) -> Self {
+        match value {
+            EventFilter::Off => LevelFilter::Off,
+            EventFilter::Error => LevelFilter::Error,
+            EventFilter::Warn => LevelFilter::Warn,
+            EventFilter::Info => LevelFilter::Info,
+            EventFilter::Debug => LevelFilter::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Throttle {
+    window_start: Timestamp,
+    sent: u32,
+    throttled: u32,
+    /// highest severity throttled this window
+    severity: EventSeverity,
+}
+
+/// Sends the records of an EventLogger as Event msgs, throttled per source
+pub struct EventService {
+    logger: &'static EventLogger,
+    max_events: u32,
+    window: Timestamp,
+    throttles: BTreeMap<String, Throttle>,
+}
+
+impl EventService {
+    pub fn new(logger: &'static EventLogger) -> Self {
+        Self::new_with_throttle(logger, DEFAULT_MAX_EVENTS, DEFAULT_THROTTLE_WINDOW)
+    }
+
+    /// At most max_events are sent per source every window microseconds
+    pub fn new_with_throttle(
+        logger: &'static EventLogger,
+        max_events: u32,
+        window: Timestamp,
+    ) -> Self {
+        Self {
+            logger,
+            max_events,
+            window,
+            throttles: BTreeMap::new(),
+        }
+    }
+
+    pub fn handle_cmd(&mut self, cmd: &EventFilterCmd) -> CmdResult {
+        self.logger.set_filter(&cmd.source, cmd.filter.into());
+        Ok(())
+    }
+
+    fn send_event(
+        rfe: &mut Rfe,
+        severity: EventSeverity,
+        source: String,
+        text: String,
+        throttled: u32,
+    ) {
+        let instance = rfe.get_instance();
+        rfe.send(Msg::Event(Event {
+            severity,
+            source,
+            instance,
+            text,
+            throttled,
+        }));
+    }
+}
+
+impl App for EventService {
+    fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
+        self.throttles.clear();
+        rfe.subscribe(TargetMsg::new(rfe.get_instance(), MsgKind::EventFilterCmd));
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        while let Some(msg) = rfe.recv() {
+            if let Msg::EventFilterCmd(cmd) = &msg.msg {
+                let result = self.handle_cmd(cmd);
+                rfe.cmd_result(&msg, result);
+            }
+        }
+
+        // report what was throttled once the window ends
+        let now = rfe.get_met_time();
+        for (source, throttle) in &mut self.throttles {
+            if now - throttle.window_start < self.window {
+                continue;
+            }
+            if throttle.throttled > 0 {
+                Self::send_event(
+                    rfe,
+                    throttle.severity,
+                    source.clone(),
+                    format!("{} events throttled", throttle.throttled),
+                    throttle.throttled,
+                );
+            }
+            *throttle = Throttle {
+                window_start: now,
+                ..Default::default()
+            };
+        }
+
+        let (events, dropped) = self.logger.take();
+        if dropped > 0 {
+            Self::send_event(
+                rfe,
+                EventSeverity::Warn,
+                "rfe".to_string(),
+                format!("{dropped} events dropped, event queue full"),
+                0,
+            );
+        }
+        for event in events {
+            let throttle = self
+                .throttles
+                .entry(event.source.clone())
+                .or_insert_with(|| Throttle {
+                    window_start: now,
+                    ..Default::default()
+                });
+            if throttle.sent < self.max_events {
+                throttle.sent += 1;
+                Self::send_event(rfe, event.severity, event.source, event.text, 0);
+            } else {
+                throttle.throttled += 1;
+                throttle.severity = throttle.severity.max(event.severity);
+            }
+        }
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz10
+    }
+}
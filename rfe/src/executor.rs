@@ -0,0 +1,290 @@
+extern crate alloc;
+extern crate std;
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use anyhow::{anyhow, Result};
+use hashbrown::HashSet;
+use log::*;
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+use crate::{
+    connector::Connector,
+    msg::{Instance, MsgPacket, TargetMsg},
+    time::TimeDriver,
+    App, RfeInstance, StopHandle, DEFAULT_APP_PRIORITY, DEFAULT_TICK_RATE,
+};
+
+/// Passed between thread groups
+pub(crate) enum GroupMsg {
+    /// sent by an app of the group
+    Local(MsgPacket),
+    /// received by the group from one of its connectors
+    Remote(MsgPacket),
+    /// subscriptions of all apps and connectors in a group
+    Subs(usize, GroupSubs),
+}
+
+/// Subscriptions a thread group sends the other groups
+#[derive(Clone, Default)]
+pub(crate) struct GroupSubs {
+    pub apps: Vec<TargetMsg>,
+    /// only those to msgs of this instance, remote msgs aren't passed between connectors
+    pub connectors: Vec<TargetMsg>,
+}
+
+/// Connects the RfeInstance of a thread group to the other groups
+pub(crate) struct GroupLink {
+    pub index: usize,
+    pub name: String,
+    /// sender of each group, None for this group
+    senders: Vec<Option<Sender<GroupMsg>>>,
+    receiver: Receiver<GroupMsg>,
+    /// last subscriptions received from each group, None until the first are received
+    subs: Vec<Option<GroupSubs>>,
+    /// apps run by the other groups
+    pub other_apps: HashSet<String>,
+}
+
+impl GroupLink {
+    /// Subscriptions of the apps in the other groups
+    pub fn get_group_subs(&self) -> impl Iterator<Item = TargetMsg> + '_ {
+        self.subs.iter().flatten().flat_map(|x| &x.apps).copied()
+    }
+
+    /// Only forwards to groups subscribed to the msg kind unless always is set,
+    /// everything is forwarded to a group until its subscriptions are received.
+    /// Local msgs also go to groups whose connectors are subscribed.
+    pub fn send(&self, msg: &MsgPacket, local: bool, always: bool) {
+        let kind = msg.msg.kind();
+        for (sender, subs) in self.senders.iter().zip(&self.subs) {
+            let Some(sender) = sender else {
+                continue;
+            };
+            if !always
+                && subs.as_ref().is_some_and(|x| {
+                    let connectors = if local { x.connectors.as_slice() } else { &[] };
+                    !x.apps.iter().chain(connectors).any(|x| x.msg == kind)
+                })
+            {
+                continue;
+            }
+            let msg = if local {
+                GroupMsg::Local(msg.clone())
+            } else {
+                GroupMsg::Remote(msg.clone())
+            };
+            // a group that already exited is ignored
+            sender.send(msg).ok();
+        }
+    }
+
+    pub fn send_subs(&self, subs: GroupSubs) {
+        for sender in self.senders.iter().flatten() {
+            sender.send(GroupMsg::Subs(self.index, subs.clone())).ok();
+        }
+    }
+
+    /// Returns the local and remote msgs from the other groups, true if their subscriptions changed
+    pub fn recv(&mut self) -> (Vec<MsgPacket>, Vec<MsgPacket>, bool) {
+        let mut local = Vec::new();
+        let mut remote = Vec::new();
+        let mut subs_updated = false;
+        while let Ok(msg) = self.receiver.try_recv() {
+            match msg {
+                GroupMsg::Local(msg) => local.push(msg),
+                GroupMsg::Remote(msg) => remote.push(msg),
+                GroupMsg::Subs(index, subs) => {
+                    self.subs[index] = Some(subs);
+                    subs_updated = true;
+                }
+            }
+        }
+        (local, remote, subs_updated)
+    }
+}
+
+/// Apps and connectors run together on one thread
+pub struct ThreadGroup<'a> {
+    name: &'a str,
+    tick_rate: u32,
+    apps: Vec<(&'a str, &'a mut (dyn App + Send), u8)>,
+    connectors: Vec<&'a mut (dyn Connector + Send)>,
+}
+
+impl<'a> ThreadGroup<'a> {
+    pub fn new(name: &'a str) -> Self {
+        Self::new_with_tick_rate(name, DEFAULT_TICK_RATE)
+    }
+
+    /// tick_rate is the frequency in Hz the group's apps are scheduled at
+    pub fn new_with_tick_rate(name: &'a str, tick_rate: u32) -> Self {
+        Self {
+            name,
+            tick_rate,
+            apps: Vec::new(),
+            connectors: Vec::new(),
+        }
+    }
+
+    pub fn add_app(&mut self, name: &'a str, app: &'a mut (dyn App + Send)) {
+        self.add_app_with_priority(name, app, DEFAULT_APP_PRIORITY);
+    }
+
+    pub fn add_app_with_priority(
+        &mut self,
+        name: &'a str,
+        app: &'a mut (dyn App + Send),
+        priority: u8,
+    ) {
+        self.apps.push((name, app, priority));
+    }
+
+    pub fn add_connector(&mut self, connector: &'a mut (dyn Connector + Send)) {
+        self.connectors.push(connector);
+    }
+
+    fn start(
+        self,
+        instance: Instance,
+        time_driver: &'a (dyn TimeDriver + Sync),
+        link: GroupLink,
+        stop: StopHandle,
+    ) -> Result<()> {
+        let _stop_on_exit = StopOnExit(stop.clone());
+        let mut rfe_instance =
+            RfeInstance::new_with_tick_rate(instance, time_driver, self.tick_rate)?;
+        rfe_instance.set_stop_handle(stop);
+        rfe_instance.set_group_link(link);
+        for (name, app, priority) in self.apps {
+            rfe_instance.add_app_with_priority(name, app, priority)?;
+        }
+        for connector in self.connectors {
+            rfe_instance.add_connector(connector);
+        }
+        rfe_instance.run_until_stopped();
+        Ok(())
+    }
+}
+
+/// Stops the other groups when a group exits early or panics
+struct StopOnExit(StopHandle);
+
+impl Drop for StopOnExit {
+    fn drop(&mut self) {
+        self.0.stop();
+    }
+}
+
+/// Runs each thread group on its own thread with its own RfeInstance.
+///
+/// Msgs are passed between the groups so apps receive the same msgs as they would
+/// running in a single RfeInstance.
+pub struct Executor<'a> {
+    instance: Instance,
+    time_driver: &'a (dyn TimeDriver + Sync),
+    groups: Vec<ThreadGroup<'a>>,
+    stop: StopHandle,
+}
+
+impl<'a> Executor<'a> {
+    pub fn new(instance: Instance, time_driver: &'a (dyn TimeDriver + Sync)) -> Self {
+        Self {
+            instance,
+            time_driver,
+            groups: Vec::new(),
+            stop: StopHandle::default(),
+        }
+    }
+
+    pub fn add_group(&mut self, group: ThreadGroup<'a>) -> Result<()> {
+        if self.groups.iter().any(|x| x.name == group.name) {
+            return Err(anyhow!("group {} already exists", group.name));
+        }
+        for (name, _, _) in &group.apps {
+            if self
+                .groups
+                .iter()
+                .flat_map(|x| &x.apps)
+                .any(|(x, _, _)| x == name)
+            {
+                return Err(anyhow!("app {name} already exists"));
+            }
+        }
+        self.groups.push(group);
+        Ok(())
+    }
+
+    pub fn get_stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Runs until the stop handle is triggered, then every group shuts down.
+    /// On linux SIGINT and SIGTERM trigger the stop handle, a second signal exits immediately.
+    pub fn start(&mut self) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        if let Err(e) = self.stop.register_signals() {
+            warn!("failed to register signal handlers {e}");
+        }
+
+        let groups = core::mem::take(&mut self.groups);
+        let (senders, receivers): (Vec<_>, Vec<_>) = groups.iter().map(|_| channel()).unzip();
+        let apps = groups
+            .iter()
+            .map(|x| x.apps.iter().map(|(name, _, _)| name.to_string()).collect())
+            .collect::<Vec<Vec<String>>>();
+
+        thread::scope(|s| {
+            let mut handles = Vec::new();
+            for (index, (group, receiver)) in groups.into_iter().zip(receivers).enumerate() {
+                let link = GroupLink {
+                    index,
+                    name: group.name.to_string(),
+                    senders: senders
+                        .iter()
+                        .enumerate()
+                        .map(|(i, x)| (i != index).then(|| x.clone()))
+                        .collect(),
+                    receiver,
+                    subs: vec![None; apps.len()],
+                    other_apps: apps
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| *i != index)
+                        .flat_map(|(_, x)| x.iter().cloned())
+                        .collect(),
+                };
+                let name = group.name;
+                let (instance, time_driver, stop) =
+                    (self.instance, self.time_driver, self.stop.clone());
+                let handle = thread::Builder::new()
+                    .name(name.to_string())
+                    .spawn_scoped(s, move || group.start(instance, time_driver, link, stop));
+                match handle {
+                    Ok(h) => handles.push((name, h)),
+                    Err(e) => {
+                        self.stop.stop();
+                        return Err(anyhow!("failed to spawn thread for group {name} {e}"));
+                    }
+                }
+            }
+
+            let mut result = Ok(());
+            for (name, handle) in handles {
+                let group_result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("thread panicked")));
+                if let Err(e) = group_result {
+                    error!("group {name} stopped: {e}");
+                    result = Err(anyhow!("group {name} stopped: {e}"));
+                }
+            }
+            result
+        })
+    }
+}
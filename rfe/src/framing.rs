@@ -0,0 +1,264 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use anyhow::{anyhow, Result};
+use bincode::{decode_from_slice, encode_to_vec};
+use core::borrow::Borrow;
+use log::*;
+
+use crate::{ccsds::CcsdsCodec, msg::MsgPacket, BINCODE_CONFIG};
+
+/// How batches of packets are turned into bytes
+#[derive(Debug, Default)]
+pub enum Encoding {
+    /// bincode of Vec<MsgPacket> with BINCODE_CONFIG
+    #[default]
+    Bincode,
+    /// consecutive CCSDS space packets. Only UdpConnector sends them bare, one batch per
+    /// datagram. Tcp and serial connectors wrap each batch in an rfe frame like any other
+    /// encoding, so ground systems expecting a bare space packet stream should use UDP
+    Ccsds(CcsdsCodec),
+}
+
+impl Encoding {
+    pub fn encode<M: Borrow<MsgPacket>>(&mut self, msgs: &[M]) -> Vec<u8> {
+        let packets = msgs
+            .iter()
+            .map(|x| self.encode_packet(x.borrow()))
+            .collect::<Vec<_>>();
+        self.join(&packets)
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> Option<Vec<MsgPacket>> {
+        match self {
+            Encoding::Bincode => {
+                match decode_from_slice::<Vec<MsgPacket>, _>(bytes, BINCODE_CONFIG) {
+                    Ok((r, _)) => Some(r),
+                    Err(e) => {
+                        warn!("failed to decode packets {e}");
+                        None
+                    }
+                }
+            }
+            Encoding::Ccsds(codec) => Some(codec.decode(bytes)),
+        }
+    }
+
+    /// One packet as it's laid out in a batch, empty if it can't be encoded
+    fn encode_packet(&mut self, msg: &MsgPacket) -> Vec<u8> {
+        match self {
+            Encoding::Bincode => {
+                encode_to_vec(msg, BINCODE_CONFIG).expect("failed to serialize packet")
+            }
+            Encoding::Ccsds(codec) => {
+                let mut buf = Vec::new();
+                codec.encode_packet(msg, &mut buf);
+                buf
+            }
+        }
+    }
+
+    /// Bytes added to a batch of count packets, the length prefix of a bincode Vec
+    fn get_batch_overhead(&self, count: usize) -> usize {
+        match self {
+            Encoding::Bincode => get_len_prefix(count).len(),
+            Encoding::Ccsds(_) => 0,
+        }
+    }
+
+    /// Batch of packets encoded with encode_packet, the same as encode gives for them
+    fn join(&self, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut buf = match self {
+            Encoding::Bincode => get_len_prefix(packets.len()),
+            Encoding::Ccsds(_) => Vec::new(),
+        };
+        for packet in packets {
+            buf.extend_from_slice(packet);
+        }
+        buf
+    }
+}
+
+fn get_len_prefix(len: usize) -> Vec<u8> {
+    encode_to_vec(len as u64, BINCODE_CONFIG).expect("failed to serialize length")
+}
+
+/// Marks the start of every frame
+pub const SYNC: [u8; 2] = [0xEB, 0x90];
+/// Frames claiming a bigger payload are treated as line noise
+pub const MAX_PAYLOAD_SIZE: usize = 64 * 1024;
+/// sync + u32 little endian payload length
+pub const HEADER_SIZE: usize = SYNC.len() + 4;
+/// u16 little endian crc of the length and payload
+pub const CRC_SIZE: usize = 2;
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for b in bytes {
+        crc ^= (*b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Wraps a payload as sync, payload length, payload, crc
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len() + CRC_SIZE);
+    frame.extend_from_slice(&SYNC);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload);
+    let crc = crc16(&frame[SYNC.len()..]);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+/// Encodes a batch of packets into frames appended to buf, starting a new frame wherever
+/// the payload would be over MAX_PAYLOAD_SIZE.
+/// A packet too large for a frame of its own is dropped and reported in the error,
+/// the others are still encoded
+pub fn encode_msgs<M: Borrow<MsgPacket>>(
+    msgs: &[M],
+    encoding: &mut Encoding,
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    let mut oversized = 0;
+    let mut batch = Vec::new();
+    let mut batch_len = 0;
+    for msg in msgs {
+        let packet = encoding.encode_packet(msg.borrow());
+        if packet.is_empty() || packet.len() + encoding.get_batch_overhead(1) > MAX_PAYLOAD_SIZE {
+            oversized += 1;
+            continue;
+        }
+        let len = batch_len + packet.len();
+        if !batch.is_empty()
+            && len + encoding.get_batch_overhead(batch.len() + 1) > MAX_PAYLOAD_SIZE
+        {
+            buf.extend(encode_frame(&encoding.join(&batch)));
+            batch.clear();
+            batch_len = 0;
+        }
+        batch_len += packet.len();
+        batch.push(packet);
+    }
+    if !batch.is_empty() {
+        buf.extend(encode_frame(&encoding.join(&batch)));
+    }
+
+    if oversized > 0 {
+        return Err(anyhow!("{oversized} msgs too large for a frame dropped"));
+    }
+    Ok(())
+}
+
+/// Reassembles frames from a byte stream that may split or merge them arbitrarily
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    dropped_bytes: u32,
+    crc_errors: u32,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            dropped_bytes: 0,
+            crc_errors: 0,
+        }
+    }
+
+    /// Adds received bytes to the reassembly buffer
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Total bytes discarded while searching for a sync marker
+    pub fn get_dropped_bytes(&self) -> u32 {
+        self.dropped_bytes
+    }
+
+    /// Frames discarded because of a bad crc
+    pub fn get_crc_errors(&self) -> u32 {
+        self.crc_errors
+    }
+
+    /// Bytes buffered waiting for the rest of a frame
+    pub fn get_pending_bytes(&self) -> u32 {
+        self.buf.len() as u32
+    }
+
+    /// Drops the buffered partial frame and resyncs, for when the rest will never arrive
+    pub fn skip_frame(&mut self) {
+        self.resync(1);
+    }
+
+    /// Drops bytes up to the next sync marker found after skip
+    fn resync(&mut self, skip: usize) {
+        let skip = skip.min(self.buf.len());
+        let start = match self.buf[skip..].windows(SYNC.len()).position(|x| x == SYNC) {
+            Some(i) => skip + i,
+            // keep a trailing byte that could be the start of a sync marker
+            None if self.buf.len() > skip && self.buf.last() == Some(&SYNC[0]) => {
+                self.buf.len() - 1
+            }
+            None => self.buf.len(),
+        };
+        self.dropped_bytes += start as u32;
+        self.buf.drain(..start);
+    }
+
+    /// Returns the next complete payload or None if more bytes are needed
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        loop {
+            if !self.buf.starts_with(&SYNC) {
+                self.resync(0);
+            }
+            if self.buf.len() < HEADER_SIZE {
+                return None;
+            }
+
+            let len =
+                u32::from_le_bytes(self.buf[SYNC.len()..HEADER_SIZE].try_into().unwrap()) as usize;
+            if len > MAX_PAYLOAD_SIZE {
+                warn!("frame length {len} too large, resyncing");
+                self.resync(1);
+                continue;
+            }
+            let end = HEADER_SIZE + len;
+            if self.buf.len() < end + CRC_SIZE {
+                return None;
+            }
+
+            let crc = u16::from_le_bytes([self.buf[end], self.buf[end + 1]]);
+            if crc != crc16(&self.buf[SYNC.len()..end]) {
+                // the sync marker may have been noise, look for a frame inside this one
+                warn!("frame crc mismatch, resyncing");
+                self.crc_errors += 1;
+                self.resync(1);
+                continue;
+            }
+
+            let payload = self.buf[HEADER_SIZE..end].to_vec();
+            self.buf.drain(..end + CRC_SIZE);
+            return Some(payload);
+        }
+    }
+
+    /// Decodes every complete frame in the buffer into packets
+    pub fn next_msgs(&mut self, encoding: &mut Encoding) -> Option<Vec<MsgPacket>> {
+        let mut msgs: Option<Vec<MsgPacket>> = None;
+        while let Some(payload) = self.next_frame() {
+            if let Some(r) = encoding.decode(&payload) {
+                msgs.get_or_insert_with(Vec::new).extend(r);
+            }
+        }
+        msgs
+    }
+}
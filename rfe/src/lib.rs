@@ -3,17 +3,26 @@
 #[cfg(feature = "std")]
 extern crate std;
 
+pub mod ccsds;
 pub mod connector;
+pub mod event;
+#[cfg(feature = "std")]
+pub mod executor;
+pub mod framing;
 use bincode::config::Configuration;
 pub mod msg;
 
 mod rfe;
 pub use rfe::*;
 
+mod app_async;
+pub use app_async::{AppFuture, AsyncApp};
+
 #[cfg(feature = "reflect")]
 pub mod reflect;
 
 pub use macros;
+pub mod reliable;
 pub mod serial;
 pub mod time;
 pub mod utils;
@@ -0,0 +1,58 @@
+use bincode::{Decode, Encode};
+extern crate alloc;
+#[cfg(feature = "reflect")]
+use crate as rfe;
+#[cfg(feature = "reflect")]
+use crate::macros::Reflect;
+use crate::time::Timestamp;
+
+use super::MsgKind;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub enum CmdState {
+    /// passed validation, the result is reported later with Executed or Failed
+    #[default]
+    Accepted,
+    Executed,
+    Failed,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub enum CmdError {
+    #[default]
+    None,
+    /// the command refers to an id, name or file that doesn't exist
+    NotFound,
+    AlreadyExists,
+    InvalidArgument,
+    /// the command isn't allowed in the app's current state
+    InvalidState,
+    /// the command was valid but carrying it out failed
+    ExecutionFailed,
+    /// app specific error code
+    Custom(u16),
+}
+
+pub type CmdResult = core::result::Result<(), CmdError>;
+
+/// Reports the progress of a command, sent from the instance that handled it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct CmdStatus {
+    pub cmd: MsgKind,
+    /// timestamp of the command packet, identifies which command this is for
+    pub cmd_timestamp: Timestamp,
+    pub state: CmdState,
+    pub error: CmdError,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct CmdCounters {
+    /// commands executed successfully
+    pub cmd_count: u32,
+    /// commands that failed
+    pub err_count: u32,
+}
@@ -4,7 +4,7 @@ extern crate alloc;
 use alloc::string::String;
 use alloc::vec::Vec;
 
-use super::{TlmSetId, TlmSetItem};
+use super::{CmdCounters, TlmSetId, TlmSetItem};
 use crate as rfe;
 #[cfg(feature = "reflect")]
 use crate::macros::Reflect;
@@ -16,6 +16,63 @@ pub struct DsTlmSet {
     pub id: TlmSetId,
     pub enabled: bool,
     pub path: String,
+    pub file_settings: DsFileSettings,
+    pub flush_policy: DsFlushPolicy,
+    /// bytes of files kept under path, the oldest are deleted past it, 0 is unlimited
+    pub quota: u64,
+    /// suspended while the disk is low on space
+    pub low_priority: bool,
+}
+
+/// When Ds flushes the buffered writes of a tlm set to its file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub enum DsFlushPolicy {
+    /// when the buffer fills, the file is closed or on a Flush cmd
+    #[default]
+    Buffered,
+    /// once this many bytes are pending
+    Bytes(u32),
+    /// this many seconds after the last flush
+    Seconds(u32),
+    /// after every record, for critical sets
+    EveryPacket,
+}
+
+/// When Ds rolls a tlm set over to a new file, a limit of 0 is unlimited
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct DsFileSettings {
+    /// bytes
+    pub max_size: u32,
+    /// seconds
+    pub max_age: u32,
+    /// rotate files when a limit is hit
+    pub enabled: bool,
+}
+
+/// File a tlm set is currently written to
+#[derive(Debug, Default, Clone, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct DsFileStatus {
+    pub id: TlmSetId,
+    /// empty until the first record is written after opening or rotating
+    pub name: String,
+    pub bytes: u32,
+    pub rotations: u32,
+    /// written but not flushed yet
+    pub pending_bytes: u32,
+    /// microseconds the last flush took
+    pub flush_time: u32,
+    pub max_flush_time: u32,
+    /// bytes of all files under the tlm set's path at the last quota check
+    pub dir_bytes: u64,
+    /// files deleted to stay within quotas
+    pub deleted_files: u32,
+    /// records dropped because a new file failed to open
+    pub open_errors: u32,
+    /// not recording because the disk is low on space
+    pub suspended: bool,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode)]
@@ -23,14 +80,22 @@ pub struct DsTlmSet {
 pub struct DsHk {
     pub perf: PerfData,
     pub counter: u32,
+    pub cmds: CmdCounters,
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode)]
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
 #[cfg_attr(feature = "reflect", derive(Reflect))]
 pub struct DsOutData {
     pub counter: u32,
     pub bytes_written: u32,
     pub bytes_written_this_cycle: u32,
+    pub files: Vec<DsFileStatus>,
+    /// bytes of all tlm set paths at the last quota check
+    pub total_bytes: u64,
+    /// lowest free space of the disks holding the tlm set paths
+    pub free_space: u64,
+    /// low priority sets are suspended
+    pub low_disk: bool,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
@@ -45,4 +110,6 @@ pub enum DsCmd {
     RemoveTlmSet(TlmSetId),
     DisableTlmSet(TlmSetId),
     EnablTlmSet(TlmSetId),
+    /// flushes the files of every tlm set
+    Flush,
 }
@@ -0,0 +1,54 @@
+use bincode::{Decode, Encode};
+extern crate alloc;
+#[cfg(feature = "reflect")]
+use crate as rfe;
+#[cfg(feature = "reflect")]
+use crate::macros::Reflect;
+use alloc::string::String;
+
+use super::Instance;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub enum EventSeverity {
+    /// trace and debug records
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+/// A log record sent as telemetry
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct Event {
+    pub severity: EventSeverity,
+    /// first part of the record's target, the app's crate unless the log call sets a target
+    pub source: String,
+    pub instance: Instance,
+    pub text: String,
+    /// events from the source dropped by throttling since the last one sent
+    pub throttled: u32,
+}
+
+/// Lowest severity of the events sent
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub enum EventFilter {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct EventFilterCmd {
+    /// a crate name or log target, not an app name, empty sets the filter of sources
+    /// without their own
+    pub source: String,
+    pub filter: EventFilter,
+}
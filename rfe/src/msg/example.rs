@@ -5,11 +5,14 @@ use crate::macros::Reflect;
 use crate::utils::PerfData;
 use bincode::{Decode, Encode};
 
+use super::CmdCounters;
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode)]
 #[cfg_attr(feature = "reflect", derive(Reflect))]
 pub struct ExampleHk {
     pub perf: PerfData,
     pub counter: u32,
+    pub cmds: CmdCounters,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode)]
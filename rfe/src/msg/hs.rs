@@ -7,6 +7,8 @@ use crate as rfe;
 use crate::macros::Reflect;
 use alloc::vec::Vec;
 
+use super::CmdCounters;
+
 #[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
 #[cfg_attr(feature = "reflect", derive(Reflect))]
 pub struct HsHk {
@@ -16,7 +18,7 @@ pub struct HsHk {
     pub mem_usage: u8,
     pub fs_usage: Vec<u8>,
     pub temps: Vec<i8>,
-    pub cmd_counter: u8,
+    pub cmds: CmdCounters,
     pub cpu_usage_enabled: bool,
     pub mem_usage_enabled: bool,
     pub fs_usage_enabled: bool,
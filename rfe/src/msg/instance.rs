@@ -0,0 +1,164 @@
+use bincode::{Decode, Encode};
+extern crate alloc;
+#[cfg(feature = "reflect")]
+use crate as rfe;
+#[cfg(feature = "reflect")]
+use crate::macros::Reflect;
+use crate::Rate;
+
+use super::CmdCounters;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct AppHk {
+    pub name: String,
+    /// lower values run first
+    pub priority: u8,
+    pub app_rate: Rate,
+    pub hk_rate: Rate,
+    pub out_data_rate: Rate,
+    pub run_count: u32,
+    /// time spent in the last call of run, hk and out_data in microseconds
+    pub run_time: u32,
+    pub hk_time: u32,
+    pub out_data_time: u32,
+    /// longest time spent in the app in a single cycle since the last RfeInstanceHk
+    pub max_cycle_time: u32,
+    pub queue_depth: u32,
+    pub queue_limit: u32,
+    /// most msgs queued at once since start
+    pub queue_high_water: u32,
+    /// msgs dropped because the queue was full or replaced by a newer one with KeepLatest
+    pub queue_dropped: u32,
+    pub state: AppState,
+    pub cmds: CmdCounters,
+    /// microseconds the app may spend in a cycle, 0 if it has no budget
+    pub time_budget: u32,
+    pub overrun_response: OverrunResponse,
+    /// cycles that went over the time budget since start
+    pub overruns: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct ConnectorHk {
+    pub msgs_sent: u32,
+    pub msgs_received: u32,
+    pub subs_received: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct RfeInstanceHk {
+    pub sch_counter: u64,
+    /// frames that took longer than the tick period
+    pub sch_overruns: u32,
+    /// times start fell behind the tick rate and skipped ahead
+    pub sch_slips: u32,
+    /// instance commands such as ReinitApp and SetAppRateCmd
+    pub cmds: CmdCounters,
+    /// in the order the apps run
+    pub apps: Vec<AppHk>,
+    pub connectors: Vec<ConnectorHk>,
+    /// thread group the hk is from, empty when the instance isn't run by an Executor
+    pub group: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub enum RateKind {
+    #[default]
+    App,
+    Hk,
+    OutData,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct SetAppRateCmd {
+    pub app_name: String,
+    pub kind: RateKind,
+    pub rate: Rate,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub enum AppState {
+    #[default]
+    Running,
+    Stopped,
+}
+
+/// What happens to msgs for an app while it is stopped
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub enum StoppedQueuePolicy {
+    /// queued msgs are dropped and new msgs are not queued
+    #[default]
+    Discard,
+    /// msgs keep being queued up to the queue depth, the app gets them once started
+    Keep,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct StopAppCmd {
+    pub app_name: String,
+    pub queue_policy: StoppedQueuePolicy,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct StartAppCmd {
+    pub app_name: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct RemoveAppCmd {
+    pub app_name: String,
+}
+
+/// What the instance does when an app goes over its time budget
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub enum OverrunResponse {
+    /// only counted and logged
+    #[default]
+    Log,
+    /// the app isn't called on the next tick it is due
+    SkipNext,
+    /// the app is stopped, its queued msgs are discarded
+    Stop,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct SetTimeBudgetCmd {
+    pub app_name: String,
+    /// microseconds, 0 removes the budget
+    pub time_budget: u32,
+    pub response: OverrunResponse,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct AppOverrun {
+    pub name: String,
+    pub cycle_time: u32,
+    pub time_budget: u32,
+}
+
+/// Sent when a frame takes longer than the tick period
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct DeadlineMiss {
+    pub sch_counter: u64,
+    /// microseconds
+    pub frame_time: u32,
+    pub tick_period: u32,
+    /// apps that went over their time budget this frame
+    pub overruns: Vec<AppOverrun>,
+}
@@ -13,12 +13,22 @@ mod to;
 pub use to::*;
 mod ds;
 pub use ds::*;
+mod instance;
+pub use instance::*;
+mod cmd;
+pub use cmd::*;
+mod event;
+pub use event::*;
 
 use crate as rfe;
 #[cfg(feature = "reflect")]
 use crate::macros::Reflect;
 use crate::time::Timestamp;
 
+/// Hash of the wire type definitions, changes when msgs or the types in them are added,
+/// removed or changed
+pub const SCHEMA_HASH: u64 = include!(concat!(env!("OUT_DIR"), "/schema_hash.rs"));
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
 #[cfg_attr(feature = "reflect", derive(Reflect))]
 pub struct TargetMsg {
@@ -65,6 +75,20 @@ pub enum Msg {
     ToHk(ToHk),
     ToOutData(ToOutData),
     ToCmd(ToCmd),
+    // new msgs go at the end, the variant index is the encoded msg id
+    ReinitAppStatus(ReinitAppStatus),
+    RfeInstanceHk(RfeInstanceHk),
+    SetAppRateCmd(SetAppRateCmd),
+    StopApp(StopAppCmd),
+    StartApp(StartAppCmd),
+    RemoveApp(RemoveAppCmd),
+    SetTimeBudgetCmd(SetTimeBudgetCmd),
+    DeadlineMiss(DeadlineMiss),
+    Event(Event),
+    EventFilterCmd(EventFilterCmd),
+    ReliableCmd(ReliableCmd),
+    ReliableAck(ReliableAck),
+    CmdStatus(CmdStatus),
 }
 
 #[derive(Debug, Clone, PartialEq, Encode, Decode)]
@@ -102,7 +126,33 @@ pub struct SubList {
 #[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
 #[cfg_attr(feature = "reflect", derive(Reflect))]
 pub struct ReinitAppCmd {
-    app_name: String,
+    pub app_name: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct ReinitAppStatus {
+    pub app_name: String,
+    pub success: bool,
+}
+
+/// A packet sent by a ReliableConnector, acknowledged by the receiving ReliableConnector
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct ReliableCmd {
+    pub session: u64,
+    pub seq: u32,
+    /// the wrapped MsgPacket encoded with BINCODE_CONFIG
+    pub packet: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct ReliableAck {
+    pub session: u64,
+    pub seq: u32,
+    /// false requests a retransmit
+    pub accepted: bool,
 }
 
 pub type TlmSetId = u16;
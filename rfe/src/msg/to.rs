@@ -6,7 +6,7 @@ use crate as rfe;
 use crate::macros::Reflect;
 use alloc::vec::Vec;
 
-use super::{TlmSetId, TlmSetItem};
+use super::{CmdCounters, TlmSetId, TlmSetItem};
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Encode, Decode)]
 #[cfg_attr(feature = "reflect", derive(Reflect))]
@@ -21,6 +21,7 @@ pub struct ToTlmSet {
 pub struct ToHk {
     pub perf: PerfData,
     pub counter: u32,
+    pub cmds: CmdCounters,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Encode, Decode)]
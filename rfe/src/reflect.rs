@@ -1,13 +1,16 @@
 extern crate alloc;
+use core::fmt::Debug;
 use core::mem::zeroed;
-use std::string::ToString;
 
 use alloc::boxed::Box;
-use alloc::string::String;
+use alloc::format;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use hashbrown::HashMap;
 
-pub trait Reflect: std::fmt::Debug {
+use crate::msg::MsgPacket;
+
+pub trait Reflect: Debug {
     fn reflect_type(&self) -> ReflectType;
     fn type_name(&self) -> &str;
     fn fields(&mut self) -> Vec<(&str, &mut dyn Reflect)>;
@@ -181,36 +184,36 @@ pub fn path_get<'a>(reflect: &'a mut dyn Reflect, path: &str) -> Option<&'a mut
     Some(next)
 }
 
-fn _flatten<'a>(reflect: &'a mut dyn Reflect) -> HashMap<String, &'a mut dyn Reflect> {
-    let mut list = HashMap::new();
+fn _flatten(reflect: &mut dyn Reflect) -> Vec<(String, &mut dyn Reflect)> {
+    let mut list = Vec::new();
 
     match reflect.reflect_type() {
         ReflectType::Value => match reflect.get_value() {
             ReflectValue::Vec(_) => {
                 for (i, r) in reflect.as_vec().unwrap().into_iter().enumerate() {
-                    let map = _flatten(r);
-                    for (sn, v) in map {
-                        list.insert(alloc::format!("[{i}].{}", sn), v);
+                    for (sn, v) in _flatten(r) {
+                        list.push((format!("[{i}].{}", sn), v));
                     }
                 }
             }
             _ => {
-                list.insert("".to_string(), reflect);
+                list.push(("".to_string(), reflect));
             }
         },
         ReflectType::Enumeration => {
-            if let Some((name, r)) = reflect.unwrap_variant() {
-                let map = _flatten(r);
-                for (sn, v) in map {
-                    list.insert(alloc::format!("{}.{}", name, sn), v);
+            if reflect.unwrap_variant().is_none() {
+                // unit variants are leaves, their value is the variant name
+                list.push(("".to_string(), reflect));
+            } else if let Some((name, r)) = reflect.unwrap_variant() {
+                for (sn, v) in _flatten(r) {
+                    list.push((format!("{}.{}", name, sn), v));
                 }
             }
         }
         ReflectType::Structure => {
             for (name, r) in reflect.fields() {
-                let map = _flatten(r);
-                for (sn, v) in map {
-                    list.insert(alloc::format!("{}.{}", name, sn), v);
+                for (sn, v) in _flatten(r) {
+                    list.push((format!("{}.{}", name, sn), v));
                 }
             }
         }
@@ -219,13 +222,120 @@ fn _flatten<'a>(reflect: &'a mut dyn Reflect) -> HashMap<String, &'a mut dyn Ref
     list
 }
 
-pub fn flatten<'a>(reflect: &'a mut dyn Reflect) -> HashMap<String, &'a mut dyn Reflect> {
+fn clean_path(path: String) -> String {
+    path[..path.len() - 1].replace(".[", "[")
+}
+
+/// Same as [`flatten`] but keeps the fields in declaration order
+pub fn flatten_ordered(reflect: &mut dyn Reflect) -> Vec<(String, &mut dyn Reflect)> {
     _flatten(reflect)
         .into_iter()
-        .map(|(k, v)| (k[..k.len() - 1].to_string().replace(".[", "["), v))
+        .map(|(k, v)| (clean_path(k), v))
         .collect()
 }
 
+pub fn flatten<'a>(reflect: &'a mut dyn Reflect) -> HashMap<String, &'a mut dyn Reflect> {
+    flatten_ordered(reflect).into_iter().collect()
+}
+
+fn csv_escape(value: String) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn csv_value(reflect: &mut dyn Reflect) -> String {
+    match reflect.reflect_type() {
+        ReflectType::Value => match reflect.get_value() {
+            ReflectValue::Vec(_) => reflect
+                .as_vec()
+                .unwrap()
+                .into_iter()
+                .map(csv_value)
+                .collect::<Vec<String>>()
+                .join(";"),
+            v => v.str(),
+        },
+        ReflectType::Enumeration | ReflectType::Structure => format!("{:?}", reflect),
+    }
+}
+
+/// True if any variant of the enum carries data
+fn has_payload(reflect: &dyn Reflect) -> bool {
+    reflect
+        .variants()
+        .into_iter()
+        .any(|(_, mut v)| v.unwrap_variant().is_some())
+}
+
+fn join_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+fn _to_csv(reflect: &mut dyn Reflect, path: String, columns: &mut Vec<(String, String)>) {
+    match reflect.reflect_type() {
+        ReflectType::Value => columns.push((path, csv_escape(csv_value(reflect)))),
+        ReflectType::Structure => {
+            for (name, r) in reflect.fields() {
+                _to_csv(r, join_path(&path, name), columns);
+            }
+        }
+        ReflectType::Enumeration => {
+            let payload = has_payload(reflect);
+            let (name, value) = match reflect.unwrap_variant() {
+                Some((name, r)) => (name.to_string(), csv_value(r)),
+                None => (format!("{:?}", reflect), String::new()),
+            };
+            columns.push((path.clone(), csv_escape(name)));
+            if payload {
+                columns.push((join_path(&path, "value"), csv_escape(value)));
+            }
+        }
+    }
+}
+
+/// Flattens `reflect` into `(column, value)` pairs in field declaration order.
+///
+/// The columns only depend on the type, not the value, so every sample of a type lines up:
+/// vecs are kept in a single column with their elements separated by `;`,
+/// enums have a column with the variant name and, if any variant carries data,
+/// a `.value` column with the data of the variant, empty for unit variants.
+pub fn to_csv(reflect: &mut dyn Reflect) -> Vec<(String, String)> {
+    let mut columns = Vec::new();
+    _to_csv(reflect, String::new(), &mut columns);
+    columns
+}
+
+impl MsgPacket {
+    /// Csv columns of the packet, the same for every packet of a MsgKind.
+    /// The msg is expanded into the fields of its variant, e.g. `msg.HsHk.perf.elapsed`
+    pub fn to_csv(&mut self) -> Vec<(String, String)> {
+        let mut columns = Vec::new();
+        _to_csv(&mut self.instance, "instance".to_string(), &mut columns);
+        match self.msg.unwrap_variant() {
+            Some((name, r)) => _to_csv(r, format!("msg.{name}"), &mut columns),
+            None => _to_csv(&mut self.msg, "msg".to_string(), &mut columns),
+        }
+        _to_csv(&mut self.timestamp, "timestamp".to_string(), &mut columns);
+        columns
+    }
+
+    /// Csv columns of the packet as `path = value`, e.g. `msg.HsHk.perf.elapsed = 120`
+    pub fn to_csv_clean(&self) -> Vec<String> {
+        self.clone()
+            .to_csv()
+            .into_iter()
+            .map(|(k, v)| format!("{k} = {v}"))
+            .collect()
+    }
+}
+
 impl Reflect for u8 {
     fn reflect_type(&self) -> ReflectType {
         ReflectType::Value
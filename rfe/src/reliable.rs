@@ -0,0 +1,273 @@
+extern crate alloc;
+use alloc::{collections::BTreeMap, rc::Rc, vec::Vec};
+use bincode::{decode_from_slice, encode_to_vec};
+use hashbrown::HashSet;
+use log::*;
+
+use crate::{
+    connector::Connector,
+    msg::{Instance, Msg, MsgKind, MsgPacket, ReliableAck, ReliableCmd},
+    BINCODE_CONFIG,
+};
+
+/// recv calls to wait for an ack before the first retransmit, doubles every retransmit
+const ACK_TIMEOUT: u32 = 10;
+const MAX_ACK_TIMEOUT: u32 = 400;
+const MAX_ATTEMPTS: u32 = 8;
+/// how many sequence numbers below the highest received are checked for duplicates
+const DUPLICATE_WINDOW: u32 = 64;
+
+#[derive(Debug)]
+struct PendingCmd {
+    packet: Rc<MsgPacket>,
+    timeout: u32,
+    countdown: u32,
+    attempts: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReliableStats {
+    pub sent: u32,
+    pub retransmits: u32,
+    pub failed: u32,
+    pub duplicates: u32,
+}
+
+/// Wraps a connector so packets of the given kinds are acknowledged and retransmitted.
+///
+/// Both ends of the link need a ReliableConnector. Sequence numbers restart with a new
+/// session, taken from the timestamp of the first reliable packet sent.
+#[derive(Debug)]
+pub struct ReliableConnector<C: Connector> {
+    inner: C,
+    kinds: HashSet<MsgKind>,
+    session: Option<u64>,
+    next_seq: u32,
+    pending: BTreeMap<u32, PendingCmd>,
+    remote_session: Option<u64>,
+    highest_seq: u32,
+    /// bit n set if highest_seq - n was received
+    received: u64,
+    stats: ReliableStats,
+}
+
+impl<C: Connector> ReliableConnector<C> {
+    pub fn new<T: IntoIterator<Item = MsgKind>>(inner: C, kinds: T) -> Self {
+        Self {
+            inner,
+            kinds: kinds.into_iter().collect(),
+            session: None,
+            next_seq: 0,
+            pending: BTreeMap::new(),
+            remote_session: None,
+            highest_seq: 0,
+            received: 0,
+            stats: Default::default(),
+        }
+    }
+
+    pub fn get_stats(&self) -> ReliableStats {
+        self.stats
+    }
+
+    pub fn get_inner(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    /// Commands sent but not acknowledged yet
+    pub fn get_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn wrap(&mut self, msg: &MsgPacket) -> Rc<MsgPacket> {
+        let session = *self.session.get_or_insert(msg.timestamp);
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let packet = Rc::new(MsgPacket::new(
+            msg.instance,
+            Msg::ReliableCmd(ReliableCmd {
+                session,
+                seq,
+                packet: encode_to_vec(msg, BINCODE_CONFIG).expect("failed to serialize packet"),
+            }),
+            msg.timestamp,
+        ));
+        self.pending.insert(
+            seq,
+            PendingCmd {
+                packet: packet.clone(),
+                timeout: ACK_TIMEOUT,
+                countdown: ACK_TIMEOUT,
+                attempts: 1,
+            },
+        );
+        self.stats.sent += 1;
+        packet
+    }
+
+    fn retransmit(&mut self) {
+        let mut to_send = Vec::new();
+        let mut failed = Vec::new();
+        for (seq, cmd) in &mut self.pending {
+            cmd.countdown = cmd.countdown.saturating_sub(1);
+            if cmd.countdown > 0 {
+                continue;
+            }
+            if cmd.attempts >= MAX_ATTEMPTS {
+                error!("reliable cmd {seq} not acknowledged after {MAX_ATTEMPTS} attempts");
+                failed.push(*seq);
+                continue;
+            }
+            cmd.attempts += 1;
+            cmd.timeout = (cmd.timeout * 2).min(MAX_ACK_TIMEOUT);
+            cmd.countdown = cmd.timeout;
+            to_send.push(cmd.packet.clone());
+        }
+
+        for seq in failed {
+            self.pending.remove(&seq);
+            self.stats.failed += 1;
+        }
+        if !to_send.is_empty() {
+            self.stats.retransmits += to_send.len() as u32;
+            self.inner.send(&to_send);
+        }
+    }
+
+    fn handle_ack(&mut self, ack: &ReliableAck) {
+        if Some(ack.session) != self.session {
+            return;
+        }
+        if ack.accepted {
+            self.pending.remove(&ack.seq);
+        } else if let Some(cmd) = self.pending.get_mut(&ack.seq) {
+            // retransmit on the next recv
+            cmd.countdown = 1;
+        }
+    }
+
+    /// Records seq as received, returns false if it was already received
+    fn check_duplicate(&mut self, session: u64, seq: u32) -> bool {
+        if self.remote_session != Some(session) {
+            self.remote_session = Some(session);
+            self.highest_seq = seq;
+            self.received = 1;
+            return true;
+        }
+
+        if seq > self.highest_seq {
+            let shift = seq - self.highest_seq;
+            self.received = if shift >= 64 {
+                0
+            } else {
+                self.received << shift
+            };
+            self.received |= 1;
+            self.highest_seq = seq;
+            return true;
+        }
+
+        let diff = self.highest_seq - seq;
+        if diff >= DUPLICATE_WINDOW || self.received & (1 << diff) != 0 {
+            return false;
+        }
+        self.received |= 1 << diff;
+        true
+    }
+
+    fn ack(session: u64, seq: u32, accepted: bool) -> Rc<MsgPacket> {
+        Rc::new(MsgPacket::new(
+            Instance::None,
+            Msg::ReliableAck(ReliableAck {
+                session,
+                seq,
+                accepted,
+            }),
+            0,
+        ))
+    }
+
+    fn handle_cmd(
+        &mut self,
+        cmd: &ReliableCmd,
+        acks: &mut Vec<Rc<MsgPacket>>,
+    ) -> Option<MsgPacket> {
+        let packet = match decode_from_slice::<MsgPacket, _>(&cmd.packet, BINCODE_CONFIG) {
+            Ok((p, _)) => p,
+            Err(e) => {
+                warn!("failed to decode reliable cmd {} {e}", cmd.seq);
+                acks.push(Self::ack(cmd.session, cmd.seq, false));
+                return None;
+            }
+        };
+
+        // ask for anything skipped over without waiting on the sender's timeout
+        if self.remote_session == Some(cmd.session) && cmd.seq > self.highest_seq {
+            let first_missing =
+                (self.highest_seq + 1).max(cmd.seq.saturating_sub(DUPLICATE_WINDOW));
+            for seq in first_missing..cmd.seq {
+                acks.push(Self::ack(cmd.session, seq, false));
+            }
+        }
+
+        acks.push(Self::ack(cmd.session, cmd.seq, true));
+        if self.check_duplicate(cmd.session, cmd.seq) {
+            Some(packet)
+        } else {
+            self.stats.duplicates += 1;
+            None
+        }
+    }
+}
+
+impl<C: Connector> Connector for ReliableConnector<C> {
+    fn send(&mut self, msgs: &[Rc<MsgPacket>]) {
+        let msgs = msgs
+            .iter()
+            .map(|x| {
+                if self.kinds.contains(&x.msg.kind()) {
+                    self.wrap(x)
+                } else {
+                    x.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        self.inner.send(&msgs);
+    }
+
+    fn recv(&mut self) -> Option<Vec<MsgPacket>> {
+        self.retransmit();
+
+        let msgs = self.inner.recv()?;
+        let mut out = Vec::new();
+        let mut acks = Vec::new();
+        for msg in msgs {
+            match &msg.msg {
+                Msg::ReliableAck(ack) => self.handle_ack(ack),
+                Msg::ReliableCmd(cmd) => {
+                    if let Some(packet) = self.handle_cmd(cmd, &mut acks) {
+                        out.push(packet);
+                    }
+                }
+                _ => out.push(msg),
+            }
+        }
+
+        if !acks.is_empty() {
+            self.inner.send(&acks);
+        }
+        if out.is_empty() {
+            None
+        } else {
+            Some(out)
+        }
+    }
+
+    /// Commands still waiting on an ack aren't retransmitted
+    fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            warn!("{} reliable cmds not acknowledged", self.pending.len());
+        }
+        self.inner.flush();
+    }
+}
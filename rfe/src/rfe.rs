@@ -2,15 +2,28 @@ extern crate alloc;
 use core::cell::RefCell;
 
 use alloc::rc::Rc;
-use alloc::{collections::vec_deque::VecDeque, vec::Vec};
+use alloc::{collections::vec_deque::VecDeque, string::String, vec::Vec};
 use anyhow::{anyhow, Result};
+use bincode::{Decode, Encode};
 use hashbrown::{HashMap, HashSet};
 use log::*;
 
+#[cfg(feature = "reflect")]
+use crate as rfe;
+#[cfg(feature = "std")]
+use crate::executor::{GroupLink, GroupSubs};
+#[cfg(feature = "reflect")]
+use crate::macros::Reflect;
 use crate::{
+    app_async::{block_on, AppFuture},
     connector::Connector,
-    msg::{Instance, Msg, MsgPacket, SubList, TargetMsg},
-    time::{TimeData, TimeDriver},
+    msg::{
+        AppHk, AppOverrun, AppState, CmdCounters, CmdError, CmdResult, CmdState, CmdStatus,
+        ConnectorHk, DeadlineMiss, Instance, Msg, MsgKind, MsgPacket, OverrunResponse, RateKind,
+        ReinitAppCmd, ReinitAppStatus, RemoveAppCmd, RfeInstanceHk, SetAppRateCmd,
+        SetTimeBudgetCmd, StartAppCmd, StopAppCmd, StoppedQueuePolicy, SubList, TargetMsg,
+    },
+    time::{TimeData, TimeDriver, Timestamp},
 };
 
 pub trait Hk: Sized + Clone + Copy + 'static + Send + Sync {}
@@ -19,14 +32,96 @@ impl<T> Hk for T where T: Sized + Clone + Copy + 'static + Send + Sync {}
 pub trait OutData: Sized + Clone + Copy + 'static + Send + Sync {}
 impl<T> OutData for T where T: Sized + Clone + Copy + 'static + Send + Sync {}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
 pub enum Rate {
+    #[default]
     Hz1,
     Hz5,
     Hz10,
     Hz20,
     Hz50,
     Hz100,
+    Hz200,
+    Hz400,
+    /// period in microseconds
+    Custom(u32),
+}
+
+/// Default scheduler tick rate in Hz
+pub const DEFAULT_TICK_RATE: u32 = 100;
+/// Priority of apps added with add_app
+pub const DEFAULT_APP_PRIORITY: u8 = 128;
+/// Default number of msgs an app can have waiting in its queue
+pub const DEFAULT_QUEUE_DEPTH: usize = 256;
+
+/// What happens to a subscribed msg when the app's queue is full.
+///
+/// Ordered from the least to the most strict, an app subscribed to a msg more than once,
+/// such as from All and its own instance, gets the strictest of its policies.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OverflowPolicy {
+    /// the oldest queued msg is dropped to make room
+    #[default]
+    DropOldest,
+    /// the new msg is dropped
+    DropNewest,
+    /// only the latest msg of the subscription is kept queued, drops the oldest when full
+    KeepLatest,
+}
+
+impl Rate {
+    /// Period in microseconds
+    pub fn get_period(&self) -> u64 {
+        match self {
+            Rate::Hz1 => 1_000_000,
+            Rate::Hz5 => 200_000,
+            Rate::Hz10 => 100_000,
+            Rate::Hz20 => 50_000,
+            Rate::Hz50 => 20_000,
+            Rate::Hz100 => 10_000,
+            Rate::Hz200 => 5_000,
+            Rate::Hz400 => 2_500,
+            Rate::Custom(period) => *period as u64,
+        }
+    }
+
+    /// Number of scheduler ticks between runs, at least 1. Apps can't be added with rates
+    /// faster than the tick rate or that aren't a whole number of ticks
+    pub fn get_divisor(&self, tick_rate: u32) -> u64 {
+        (self.get_period() * tick_rate as u64 / 1_000_000).max(1)
+    }
+
+    /// True if the rate has a shorter period than a tick
+    pub fn is_faster_than(&self, tick_rate: u32) -> bool {
+        self.get_period() * (tick_rate as u64) < 1_000_000
+    }
+
+    /// True if the period is a whole number of ticks, otherwise the divisor would truncate
+    /// and the rate would run faster than asked
+    pub fn is_whole_ticks(&self, tick_rate: u32) -> bool {
+        (self.get_period() * tick_rate as u64).is_multiple_of(1_000_000)
+    }
+
+    /// Apps can only be scheduled at rates that are a whole number of ticks
+    fn check(&self, tick_rate: u32) -> Result<()> {
+        if self.is_faster_than(tick_rate) {
+            return Err(anyhow!(
+                "{self:?} is faster than the {tick_rate} Hz tick rate"
+            ));
+        }
+        if !self.is_whole_ticks(tick_rate) {
+            return Err(anyhow!(
+                "{self:?} isn't a whole number of ticks at {tick_rate} Hz"
+            ));
+        }
+        Ok(())
+    }
+
+    /// if a task at this rate should run on this tick, phase shifts the tick it runs on
+    fn is_due(&self, tick_rate: u32, sch_counter: u64, phase: u64) -> bool {
+        (sch_counter + phase).is_multiple_of(self.get_divisor(tick_rate))
+    }
 }
 
 type RfeTimeRef<'a> = Rc<RefCell<RfeTime<'a>>>;
@@ -36,30 +131,116 @@ pub struct RfeTime<'a> {
     time_driver: &'a dyn TimeDriver,
 }
 
+impl RfeTime<'_> {
+    fn met_time(&self) -> Timestamp {
+        self.time_driver.get_monotonic_time(self.time_data)
+    }
+
+    fn system_time(&self) -> Timestamp {
+        self.time_driver.get_system_time(self.time_data)
+    }
+}
+
 pub struct RfeInstance<'a> {
-    app_list: HashMap<&'a str, AppRef<'a>>,
+    app_list: Vec<AppRef<'a>>,
     time: RfeTimeRef<'a>,
     #[allow(dead_code)]
     instance: Instance,
     connectors: Vec<ConnectorState<'a>>,
     sch_counter: u64,
+    sch_overruns: u32,
+    sch_slips: u32,
+    #[cfg(feature = "std")]
+    stop: StopHandle,
+    #[cfg(feature = "std")]
+    group: Option<GroupLink>,
+    tick_rate: u32,
+    msgs_to_send: Vec<MsgPacket>,
+    cmd_counters: CmdCounters,
+    routes: HashMap<MsgKind, Route>,
+    /// set when connector subscriptions change or connectors are added
+    routes_outdated: bool,
+}
+
+/// Subscribers of a single msg kind, apps and connectors are indices into their lists
+#[derive(Debug, Default)]
+struct Route {
+    /// apps receiving msgs sent from this instance
+    local: Vec<(usize, OverflowPolicy)>,
+    /// apps receiving msgs of any instance from connectors
+    remote_any: Vec<(usize, OverflowPolicy)>,
+    /// apps receiving msgs of one instance from connectors, apps in remote_any are left out
+    remote: Vec<(Instance, usize, OverflowPolicy)>,
+    /// connectors msgs sent from this instance are forwarded to
+    connectors: Vec<usize>,
 }
 
 pub struct AppRef<'a> {
+    name: &'a str,
+    priority: u8,
+    state: AppState,
+    time_budget: Option<u32>,
+    overrun_response: OverrunResponse,
+    /// set by OverrunResponse::SkipNext
+    skip_next: bool,
     app: &'a mut dyn App,
     app_rate: Rate,
     out_data_rate: Rate,
     hk_rate: Rate,
+    phase: u64,
     rfe: Rfe<'a>,
+    hk: AppHk,
+}
+
+impl AppRef<'_> {
+    fn stop(&mut self, queue_policy: StoppedQueuePolicy) {
+        self.state = AppState::Stopped;
+        if queue_policy == StoppedQueuePolicy::Discard {
+            self.rfe.msgs_recevied.clear();
+            self.rfe.discard_msgs = true;
+        }
+    }
+
+    /// Counts and responds to a cycle over the app's time budget
+    fn check_budget(&mut self, cycle_time: u32) -> Option<AppOverrun> {
+        let time_budget = self.time_budget?;
+        if cycle_time <= time_budget {
+            return None;
+        }
+        self.hk.overruns += 1;
+        warn!(
+            "app {} took {cycle_time} us, over its {time_budget} us budget",
+            self.name
+        );
+        match self.overrun_response {
+            OverrunResponse::Log => (),
+            OverrunResponse::SkipNext => self.skip_next = true,
+            OverrunResponse::Stop => {
+                error!("stopping app {} after overrunning its budget", self.name);
+                self.stop(StoppedQueuePolicy::Discard);
+            }
+        }
+        Some(AppOverrun {
+            name: self.name.into(),
+            cycle_time,
+            time_budget,
+        })
+    }
 }
 
 pub struct Rfe<'a> {
-    subscriptions: HashSet<TargetMsg>,
+    subscriptions: HashMap<TargetMsg, OverflowPolicy>,
     msgs_to_send: Vec<MsgPacket>,
-    msgs_recevied: VecDeque<MsgPacket>,
+    msgs_recevied: VecDeque<Rc<MsgPacket>>,
     instance: Instance,
     time: RfeTimeRef<'a>,
     subs_updated: bool,
+    cmd_counters: CmdCounters,
+    queue_depth: usize,
+    queue_high_water: u32,
+    queue_dropped: u32,
+    /// set while the app is stopped with StoppedQueuePolicy::Discard
+    discard_msgs: bool,
 }
 
 #[derive(Debug)]
@@ -68,17 +249,23 @@ pub struct ConnectorState<'a> {
     subscriptions: HashSet<TargetMsg>,
     subs_received: bool,
     subs_last_requested: u64,
+    hk: ConnectorHk,
 }
 
 impl<'a> Rfe<'a> {
     pub fn new(instance: Instance, time: RfeTimeRef<'a>) -> Self {
         Self {
-            subscriptions: HashSet::new(),
+            subscriptions: HashMap::new(),
             msgs_to_send: Vec::new(),
             msgs_recevied: VecDeque::new(),
             instance,
             subs_updated: false,
             time,
+            cmd_counters: Default::default(),
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            queue_high_water: 0,
+            queue_dropped: 0,
+            discard_msgs: false,
         }
     }
 
@@ -87,12 +274,17 @@ impl<'a> Rfe<'a> {
     }
 
     pub fn subscribe(&mut self, msg: TargetMsg) {
-        self.subscriptions.insert(msg);
+        self.subscribe_with_policy(msg, OverflowPolicy::default());
+    }
+
+    pub fn subscribe_with_policy(&mut self, msg: TargetMsg, policy: OverflowPolicy) {
+        self.subscriptions.insert(msg, policy);
         self.subs_updated = true;
     }
 
     pub fn subscribe_all<T: IntoIterator<Item = TargetMsg>>(&mut self, msgs: T) {
-        self.subscriptions.extend(msgs.into_iter());
+        self.subscriptions
+            .extend(msgs.into_iter().map(|x| (x, OverflowPolicy::default())));
         self.subs_updated = true;
     }
 
@@ -120,13 +312,101 @@ impl<'a> Rfe<'a> {
     }
 
     pub fn post_message(&mut self, msg: MsgPacket) {
+        self.post_shared(Rc::new(msg), OverflowPolicy::default());
+    }
+
+    fn post_shared(&mut self, msg: Rc<MsgPacket>, policy: OverflowPolicy) {
+        if self.discard_msgs {
+            return;
+        }
+        if policy == OverflowPolicy::KeepLatest {
+            let target = msg.to_target();
+            let len = self.msgs_recevied.len();
+            self.msgs_recevied.retain(|x| x.to_target() != target);
+            self.queue_dropped += (len - self.msgs_recevied.len()) as u32;
+        }
+        if self.msgs_recevied.len() >= self.queue_depth {
+            self.queue_dropped += 1;
+            if policy == OverflowPolicy::DropNewest {
+                return;
+            }
+            self.msgs_recevied.pop_front();
+        }
         self.msgs_recevied.push_back(msg);
+        self.queue_high_water = self.queue_high_water.max(self.msgs_recevied.len() as u32);
+    }
+
+    /// Max msgs waiting in the queue, the oldest msgs are dropped if more are already queued
+    pub fn set_queue_depth(&mut self, depth: usize) {
+        self.queue_depth = depth.max(1);
+        while self.msgs_recevied.len() > self.queue_depth {
+            self.msgs_recevied.pop_front();
+            self.queue_dropped += 1;
+        }
     }
 
-    pub fn recv(&mut self) -> Option<MsgPacket> {
+    pub fn get_queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    /// msgs dropped from the queue since start, by overflow or replaced with KeepLatest
+    pub fn get_queue_dropped(&self) -> u32 {
+        self.queue_dropped
+    }
+
+    /// msgs are shared between subscribers and connectors, clone the packet to keep it mutable
+    pub fn recv(&mut self) -> Option<Rc<MsgPacket>> {
         self.msgs_recevied.pop_front()
     }
 
+    /// Reports that cmd passed validation, its result is reported later with cmd_executed or cmd_failed
+    pub fn cmd_accepted(&mut self, cmd: &MsgPacket) {
+        self.send_cmd_status(cmd, CmdState::Accepted, CmdError::None);
+    }
+
+    pub fn cmd_executed(&mut self, cmd: &MsgPacket) {
+        self.cmd_counters.cmd_count += 1;
+        self.send_cmd_status(cmd, CmdState::Executed, CmdError::None);
+    }
+
+    pub fn cmd_failed(&mut self, cmd: &MsgPacket, error: CmdError) {
+        self.cmd_counters.err_count += 1;
+        self.send_cmd_status(cmd, CmdState::Failed, error);
+    }
+
+    /// Reports the result of a command that was handled immediately
+    pub fn cmd_result(&mut self, cmd: &MsgPacket, result: CmdResult) {
+        match result {
+            Ok(_) => self.cmd_executed(cmd),
+            Err(e) => self.cmd_failed(cmd, e),
+        }
+    }
+
+    fn send_cmd_status(&mut self, cmd: &MsgPacket, state: CmdState, error: CmdError) {
+        self.send(Msg::CmdStatus(CmdStatus {
+            cmd: cmd.msg.kind(),
+            cmd_timestamp: cmd.timestamp,
+            state,
+            error,
+        }));
+    }
+
+    /// Counts of commands reported with cmd_executed and cmd_failed, for the app's hk
+    pub fn get_cmd_counters(&self) -> CmdCounters {
+        self.cmd_counters
+    }
+
+    pub fn reset_cmd_counters(&mut self) {
+        self.cmd_counters = Default::default();
+    }
+
+    /// drops all queued messages and subscriptions
+    fn reset(&mut self) {
+        self.unsubscribe_all();
+        self.msgs_to_send.clear();
+        self.msgs_recevied.clear();
+    }
+
     /// time starting from power on or program start
     pub fn get_met_time(&self) -> u64 {
         let time = self.time.borrow();
@@ -143,48 +423,144 @@ impl<'a> Rfe<'a> {
 pub trait App {
     fn init(&mut self, rfe: &mut Rfe) -> Result<()>;
     fn run(&mut self, rfe: &mut Rfe);
+
+    /// Implemented for AsyncApps, RfeInstance::run_async awaits the future in place of run
+    fn run_async<'b>(&'b mut self, _rfe: &'b mut Rfe) -> Option<AppFuture<'b>> {
+        None
+    }
+
     fn hk(&mut self, rfe: &mut Rfe);
     fn out_data(&mut self, rfe: &mut Rfe);
     fn get_app_rate(&self) -> Rate;
+
+    fn get_hk_rate(&self) -> Rate {
+        Rate::Hz1
+    }
+
+    fn get_out_data_rate(&self) -> Rate {
+        self.get_app_rate()
+    }
+
+    /// Apps running slower than the msgs they subscribe to need a deeper queue
+    fn get_queue_depth(&self) -> usize {
+        DEFAULT_QUEUE_DEPTH
+    }
+
+    /// Microseconds the app may spend in a cycle of run, hk and out_data
+    fn get_time_budget(&self) -> Option<u32> {
+        None
+    }
+
+    fn get_overrun_response(&self) -> OverrunResponse {
+        OverrunResponse::Log
+    }
+
+    /// Called once when the instance stops, after the app's final run.
+    /// Msgs sent here are still passed to the connectors.
+    fn shutdown(&mut self, _rfe: &mut Rfe) {}
 }
 
 impl<'a> RfeInstance<'a> {
     pub fn new(instance: Instance, time_driver: &'a dyn TimeDriver) -> Self {
+        Self::new_with_tick_rate(instance, time_driver, DEFAULT_TICK_RATE)
+            .expect("default tick rate is invalid")
+    }
+
+    /// tick_rate is the frequency in Hz that run is called at, from 1 Hz to 1 MHz
+    pub fn new_with_tick_rate(
+        instance: Instance,
+        time_driver: &'a dyn TimeDriver,
+        tick_rate: u32,
+    ) -> Result<Self> {
+        if tick_rate == 0 || tick_rate > 1_000_000 {
+            return Err(anyhow!(
+                "invalid tick rate {tick_rate} Hz, must be from 1 Hz to 1 MHz"
+            ));
+        }
         let time = Rc::new(RefCell::new(RfeTime {
             time_data: TimeData {
                 sch_counter: 0,
                 time_offset: 0,
+                tick_period: 1_000_000 / tick_rate as Timestamp,
             },
             time_driver,
         }));
-        Self {
-            app_list: HashMap::new(),
+        Ok(Self {
+            app_list: Vec::new(),
             instance,
             connectors: Vec::new(),
             time,
             sch_counter: 0,
-        }
+            sch_overruns: 0,
+            sch_slips: 0,
+            #[cfg(feature = "std")]
+            stop: StopHandle::default(),
+            #[cfg(feature = "std")]
+            group: None,
+            tick_rate,
+            msgs_to_send: Vec::new(),
+            cmd_counters: Default::default(),
+            routes: HashMap::new(),
+            routes_outdated: true,
+        })
     }
 
+    /// Apps run in the order they are added, after apps with a lower priority value
     pub fn add_app(&mut self, name: &'a str, app: &'a mut dyn App) -> Result<()> {
-        if self.app_list.contains_key(name) {
+        self.add_app_with_priority(name, app, DEFAULT_APP_PRIORITY)
+    }
+
+    /// Apps with a lower priority value run first each tick, apps with equal priority run in the
+    /// order they are added
+    pub fn add_app_with_priority(
+        &mut self,
+        name: &'a str,
+        app: &'a mut dyn App,
+        priority: u8,
+    ) -> Result<()> {
+        if self.get_app(name).is_some() {
             return Err(anyhow!(
                 "failed to add app {name}, already added an app with that name"
             ));
         }
         let app_rate = app.get_app_rate();
+        let hk_rate = app.get_hk_rate();
+        let out_data_rate = app.get_out_data_rate();
+        for rate in [app_rate, hk_rate, out_data_rate] {
+            rate.check(self.tick_rate)
+                .map_err(|e| anyhow!("failed to add app {name}, {e}"))?;
+        }
+        let index = self.app_list.partition_point(|x| x.priority <= priority);
+        let phase = self.app_list.len() as u64;
         self.app_list.insert(
-            name,
+            index,
             AppRef {
-                app: app,
-                app_rate: app_rate,
-                hk_rate: Rate::Hz1,
-                out_data_rate: app_rate,
+                name,
+                priority,
+                state: AppState::Running,
+                time_budget: app.get_time_budget(),
+                overrun_response: app.get_overrun_response(),
+                skip_next: false,
+                app,
+                app_rate,
+                hk_rate,
+                out_data_rate,
+                // spread apps across ticks so apps at the same rate don't all run together,
+                // set_app_phase overrides it
+                phase,
                 rfe: Rfe::new(self.instance, self.time.clone()),
+                hk: AppHk {
+                    name: name.into(),
+                    priority,
+                    ..Default::default()
+                },
             },
         );
+        // routes refer to apps by index
+        self.routes_outdated = true;
 
-        let appref = self.app_list.get_mut(name).unwrap();
+        let appref = &mut self.app_list[index];
+        appref.rfe.set_queue_depth(appref.app.get_queue_depth());
         if let Err(e) = appref.app.init(&mut appref.rfe) {
             error!("app {name} failed to initialize {e}");
         }
@@ -192,192 +568,825 @@ impl<'a> RfeInstance<'a> {
         return Ok(());
     }
 
+    fn get_app(&self, name: &str) -> Option<&AppRef<'a>> {
+        self.app_list.iter().find(|x| x.name == name)
+    }
+
+    fn get_app_mut(&mut self, name: &str) -> Option<&mut AppRef<'a>> {
+        self.app_list.iter_mut().find(|x| x.name == name)
+    }
+
+    /// Clears the app's queues and subscriptions then calls its init again
+    pub fn reinit_app(&mut self, name: &str) -> Result<()> {
+        let appref = self
+            .get_app_mut(name)
+            .ok_or(anyhow!("failed to reinit app {name}, app doesn't exist"))?;
+        appref.rfe.reset();
+        appref.app.init(&mut appref.rfe)
+    }
+
+    fn handle_reinit_app(&mut self, cmd: &ReinitAppCmd) -> CmdResult {
+        let result = match self.reinit_app(&cmd.app_name) {
+            Ok(_) => {
+                info!("app {} reinitialized", cmd.app_name);
+                Ok(())
+            }
+            Err(e) if self.get_app(&cmd.app_name).is_some() => {
+                error!("app {} failed to reinitialize {e}", cmd.app_name);
+                Err(CmdError::ExecutionFailed)
+            }
+            Err(e) => {
+                error!("{e}");
+                Err(CmdError::NotFound)
+            }
+        };
+        self.send(Msg::ReinitAppStatus(ReinitAppStatus {
+            app_name: cmd.app_name.clone(),
+            success: result.is_ok(),
+        }));
+        result
+    }
+
+    pub fn set_app_rate(&mut self, name: &str, kind: RateKind, rate: Rate) -> Result<()> {
+        let tick_rate = self.tick_rate;
+        let appref = self.get_app_mut(name).ok_or(anyhow!(
+            "failed to set rate of app {name}, app doesn't exist"
+        ))?;
+        rate.check(tick_rate)
+            .map_err(|e| anyhow!("failed to set rate of app {name}, {e}"))?;
+        match kind {
+            RateKind::App => appref.app_rate = rate,
+            RateKind::Hk => appref.hk_rate = rate,
+            RateKind::OutData => appref.out_data_rate = rate,
+        }
+        Ok(())
+    }
+
+    pub fn set_queue_depth(&mut self, name: &str, depth: usize) -> Result<()> {
+        self.get_app_mut(name)
+            .ok_or(anyhow!(
+                "failed to set queue depth of app {name}, app doesn't exist"
+            ))?
+            .rfe
+            .set_queue_depth(depth);
+        Ok(())
+    }
+
+    /// Stops calling the app, it keeps its subscriptions
+    pub fn stop_app(&mut self, name: &str, queue_policy: StoppedQueuePolicy) -> Result<()> {
+        let appref = self
+            .get_app_mut(name)
+            .ok_or(anyhow!("failed to stop app {name}, app doesn't exist"))?;
+        if appref.state == AppState::Stopped {
+            return Err(anyhow!("failed to stop app {name}, app is already stopped"));
+        }
+        appref.stop(queue_policy);
+        Ok(())
+    }
+
+    pub fn start_app(&mut self, name: &str) -> Result<()> {
+        let appref = self
+            .get_app_mut(name)
+            .ok_or(anyhow!("failed to start app {name}, app doesn't exist"))?;
+        if appref.state == AppState::Running {
+            return Err(anyhow!(
+                "failed to start app {name}, app is already running"
+            ));
+        }
+        appref.state = AppState::Running;
+        appref.skip_next = false;
+        appref.rfe.discard_msgs = false;
+        Ok(())
+    }
+
+    /// Calls shutdown on the app then removes it and its subscriptions, init is not called
+    /// again. Msgs sent from shutdown are routed on the next tick
+    pub fn remove_app(&mut self, name: &str) -> Result<()> {
+        let index = self
+            .app_list
+            .iter()
+            .position(|x| x.name == name)
+            .ok_or(anyhow!("failed to remove app {name}, app doesn't exist"))?;
+        let mut app = self.app_list.remove(index);
+        app.app.shutdown(&mut app.rfe);
+        self.msgs_to_send.append(&mut app.rfe.msgs_to_send);
+        self.routes_outdated = true;
+        // connectors need to request the new subscriptions
+        for connector_state in &mut self.connectors {
+            connector_state.subs_received = false;
+        }
+        Ok(())
+    }
+
+    /// Shifts the ticks the app runs on, apps at the same rate with the same phase run on the
+    /// same tick. Apps are given the number of apps added before them by default
+    pub fn set_app_phase(&mut self, name: &str, phase: u64) -> Result<()> {
+        let appref = self
+            .get_app_mut(name)
+            .ok_or(anyhow!("failed to set phase of {name}, app doesn't exist"))?;
+        appref.phase = phase;
+        Ok(())
+    }
+
+    /// None removes the app's budget
+    pub fn set_time_budget(
+        &mut self,
+        name: &str,
+        time_budget: Option<u32>,
+        response: OverrunResponse,
+    ) -> Result<()> {
+        let appref = self.get_app_mut(name).ok_or(anyhow!(
+            "failed to set time budget of {name}, app doesn't exist"
+        ))?;
+        appref.time_budget = time_budget;
+        appref.overrun_response = response;
+        Ok(())
+    }
+
+    fn handle_set_time_budget(&mut self, cmd: &SetTimeBudgetCmd) -> CmdResult {
+        let time_budget = (cmd.time_budget > 0).then_some(cmd.time_budget);
+        self.set_time_budget(&cmd.app_name, time_budget, cmd.response)
+            .map(|_| info!("app {} time budget set to {time_budget:?}", cmd.app_name))
+            .map_err(|e| {
+                error!("{e}");
+                CmdError::NotFound
+            })
+    }
+
+    /// NotFound if the app doesn't exist, otherwise error
+    fn app_cmd_error(&self, name: &str, error: CmdError) -> CmdError {
+        if self.get_app(name).is_some() {
+            error
+        } else {
+            CmdError::NotFound
+        }
+    }
+
+    fn handle_stop_app(&mut self, cmd: &StopAppCmd) -> CmdResult {
+        self.stop_app(&cmd.app_name, cmd.queue_policy)
+            .map(|_| info!("app {} stopped", cmd.app_name))
+            .map_err(|e| {
+                error!("{e}");
+                self.app_cmd_error(&cmd.app_name, CmdError::InvalidState)
+            })
+    }
+
+    fn handle_start_app(&mut self, cmd: &StartAppCmd) -> CmdResult {
+        self.start_app(&cmd.app_name)
+            .map(|_| info!("app {} started", cmd.app_name))
+            .map_err(|e| {
+                error!("{e}");
+                self.app_cmd_error(&cmd.app_name, CmdError::InvalidState)
+            })
+    }
+
+    fn handle_remove_app(&mut self, cmd: &RemoveAppCmd) -> CmdResult {
+        self.remove_app(&cmd.app_name)
+            .map(|_| info!("app {} removed", cmd.app_name))
+            .map_err(|e| {
+                error!("{e}");
+                CmdError::NotFound
+            })
+    }
+
+    fn handle_set_app_rate(&mut self, cmd: &SetAppRateCmd) -> CmdResult {
+        match self.set_app_rate(&cmd.app_name, cmd.kind, cmd.rate) {
+            Ok(_) => {
+                info!(
+                    "app {} {:?} rate set to {:?}",
+                    cmd.app_name, cmd.kind, cmd.rate
+                );
+                Ok(())
+            }
+            Err(e) if self.get_app(&cmd.app_name).is_some() => {
+                error!("{e}");
+                Err(CmdError::InvalidArgument)
+            }
+            Err(e) => {
+                error!("{e}");
+                Err(CmdError::NotFound)
+            }
+        }
+    }
+
+    /// handles commands addressed to the instance itself and reports their status
+    fn handle_cmd(&mut self, packet: &MsgPacket) {
+        let result = match &packet.msg {
+            Msg::ReinitApp(cmd) => self.handle_reinit_app(cmd),
+            Msg::SetAppRateCmd(cmd) => self.handle_set_app_rate(cmd),
+            Msg::StopApp(cmd) => self.handle_stop_app(cmd),
+            Msg::StartApp(cmd) => self.handle_start_app(cmd),
+            Msg::RemoveApp(cmd) => self.handle_remove_app(cmd),
+            Msg::SetTimeBudgetCmd(cmd) => self.handle_set_time_budget(cmd),
+            _ => return,
+        };
+        let (state, error) = match result {
+            Ok(_) => {
+                self.cmd_counters.cmd_count += 1;
+                (CmdState::Executed, CmdError::None)
+            }
+            Err(e) => {
+                self.cmd_counters.err_count += 1;
+                (CmdState::Failed, e)
+            }
+        };
+        self.send(Msg::CmdStatus(CmdStatus {
+            cmd: packet.msg.kind(),
+            cmd_timestamp: packet.timestamp,
+            state,
+            error,
+        }));
+    }
+
+    /// app the instance command is for, None if msg isn't an instance command
+    fn get_cmd_app_name(msg: &MsgPacket) -> Option<&str> {
+        match &msg.msg {
+            Msg::ReinitApp(cmd) => Some(&cmd.app_name),
+            Msg::SetAppRateCmd(cmd) => Some(&cmd.app_name),
+            Msg::StopApp(cmd) => Some(&cmd.app_name),
+            Msg::StartApp(cmd) => Some(&cmd.app_name),
+            Msg::RemoveApp(cmd) => Some(&cmd.app_name),
+            Msg::SetTimeBudgetCmd(cmd) => Some(&cmd.app_name),
+            _ => None,
+        }
+    }
+
+    fn is_instance_cmd(&self, msg: &MsgPacket) -> bool {
+        Self::get_cmd_app_name(msg).is_some_and(|x| self.handles_app(x))
+            && (msg.instance == self.instance || msg.instance == Instance::All)
+    }
+
+    /// With thread groups only the group running the app handles its commands,
+    /// the first group reports apps that no group runs
+    #[cfg(feature = "std")]
+    fn handles_app(&self, name: &str) -> bool {
+        match &self.group {
+            Some(group) => {
+                self.get_app(name).is_some()
+                    || (group.index == 0 && !group.other_apps.contains(name))
+            }
+            None => true,
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn handles_app(&self, _name: &str) -> bool {
+        true
+    }
+
+    /// Passes msgs to the other thread groups, local msgs were sent by this instance's apps
+    #[cfg(feature = "std")]
+    fn forward_to_groups(&self, msgs: &[Rc<MsgPacket>], local: bool) {
+        if let Some(group) = &self.group {
+            for msg in msgs {
+                let always =
+                    Self::get_cmd_app_name(msg).is_some() || matches!(msg.msg, Msg::SetTimeCmd(_));
+                group.send(msg, local, always);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn forward_to_groups(&self, _msgs: &[Rc<MsgPacket>], _local: bool) {}
+
+    /// Returns the msgs sent by apps of the other thread groups and the msgs they received
+    /// from their connectors
+    #[cfg(feature = "std")]
+    fn recv_from_groups(&mut self) -> (Vec<Rc<MsgPacket>>, Vec<Rc<MsgPacket>>) {
+        let Some(group) = &mut self.group else {
+            return (Vec::new(), Vec::new());
+        };
+        let (local, remote, subs_updated) = group.recv();
+        if subs_updated {
+            // connectors need to request the new subscriptions
+            for connector_state in &mut self.connectors {
+                connector_state.subs_received = false;
+            }
+        }
+        for msg in &remote {
+            if let Msg::SetTimeCmd(new_time) = &msg.msg {
+                self.time.borrow_mut().time_data.time_offset = *new_time;
+            }
+        }
+        (
+            local.into_iter().map(Rc::new).collect(),
+            remote.into_iter().map(Rc::new).collect(),
+        )
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn recv_from_groups(&mut self) -> (Vec<Rc<MsgPacket>>, Vec<Rc<MsgPacket>>) {
+        (Vec::new(), Vec::new())
+    }
+
+    /// queues a message from the instance itself, it is routed on the next run
+    fn send(&mut self, msg: Msg) {
+        let timestamp = self.time.borrow().system_time();
+        self.msgs_to_send
+            .push(MsgPacket::new(self.instance, msg, timestamp));
+    }
+
     pub fn add_connector(&mut self, connector: &'a mut dyn Connector) {
         self.connectors.push(ConnectorState {
             connector,
             subs_last_requested: 0,
             subs_received: false,
             subscriptions: HashSet::new(),
+            hk: Default::default(),
         });
+        self.routes_outdated = true;
     }
 
-    /// Expected to be called at 100Hz
-    pub fn run(&mut self) {
-        let mut msgs = Vec::new();
-        for app in self.app_list.values_mut() {
-            if app.app_rate == Rate::Hz100
-                || (self.sch_counter % 2 == 0 && app.app_rate == Rate::Hz50)
-                || (self.sch_counter % 5 == 0 && app.app_rate == Rate::Hz20)
-                || (self.sch_counter % 10 == 0 && app.app_rate == Rate::Hz10)
-                || (self.sch_counter % 20 == 0 && app.app_rate == Rate::Hz5)
-                || (self.sch_counter % 100 == 0 && app.app_rate == Rate::Hz1)
-            {
-                app.app.run(&mut app.rfe);
-            }
-
-            if app.hk_rate == Rate::Hz100
-                || (self.sch_counter % 2 == 0 && app.hk_rate == Rate::Hz50)
-                || (self.sch_counter % 5 == 0 && app.hk_rate == Rate::Hz20)
-                || (self.sch_counter % 10 == 0 && app.hk_rate == Rate::Hz10)
-                || (self.sch_counter % 20 == 0 && app.hk_rate == Rate::Hz5)
-                || (self.sch_counter % 100 == 0 && app.hk_rate == Rate::Hz1)
-            {
-                app.app.hk(&mut app.rfe);
+    /// Rebuilds the routing table if any app or connector subscriptions changed
+    fn update_routes(&mut self) {
+        let mut apps_updated = false;
+        for app in self.app_list.iter_mut() {
+            apps_updated |= core::mem::take(&mut app.rfe.subs_updated);
+        }
+        if apps_updated {
+            // connectors need to request the new subscriptions
+            for connector_state in &mut self.connectors {
+                connector_state.subs_received = false;
             }
+        }
+        if !apps_updated && !self.routes_outdated {
+            return;
+        }
+        self.routes_outdated = false;
+        self.routes.clear();
 
-            if app.out_data_rate == Rate::Hz100
-                || (self.sch_counter % 2 == 0 && app.out_data_rate == Rate::Hz50)
-                || (self.sch_counter % 5 == 0 && app.out_data_rate == Rate::Hz20)
-                || (self.sch_counter % 10 == 0 && app.out_data_rate == Rate::Hz10)
-                || (self.sch_counter % 20 == 0 && app.out_data_rate == Rate::Hz5)
-                || (self.sch_counter % 100 == 0 && app.out_data_rate == Rate::Hz1)
-            {
-                app.app.out_data(&mut app.rfe);
+        for (i, app) in self.app_list.iter().enumerate() {
+            for (sub, policy) in &app.rfe.subscriptions {
+                let route = self.routes.entry(sub.msg).or_default();
+                match sub.instance {
+                    Instance::All => {
+                        add_subscriber(&mut route.local, i, *policy);
+                        add_subscriber(&mut route.remote_any, i, *policy);
+                    }
+                    Instance::Other => add_subscriber(&mut route.remote_any, i, *policy),
+                    instance => {
+                        if instance == self.instance {
+                            add_subscriber(&mut route.local, i, *policy);
+                        }
+                        route.remote.push((instance, i, *policy));
+                    }
+                }
             }
-
-            let new_msgs = core::mem::take(&mut app.rfe.msgs_to_send);
-            msgs.extend(new_msgs);
+        }
+        for route in self.routes.values_mut() {
+            let Route {
+                remote, remote_any, ..
+            } = route;
+            remote.retain(|(_, i, policy)| {
+                let Some(any) = remote_any.iter_mut().find(|x| x.0 == *i) else {
+                    return true;
+                };
+                any.1 = any.1.max(*policy);
+                false
+            });
         }
 
-        for app in self.app_list.values_mut() {
-            for msg in &msgs {
-                if app
-                    .rfe
-                    .subscriptions
-                    .contains(&TargetMsg::new(self.instance, msg.msg.kind()))
-                    || app
-                        .rfe
-                        .subscriptions
-                        .contains(&TargetMsg::new(Instance::All, msg.msg.kind()))
+        for (i, connector_state) in self.connectors.iter().enumerate() {
+            for sub in &connector_state.subscriptions {
+                if sub.instance == self.instance
+                    || sub.instance == Instance::All
+                    || sub.instance == Instance::Other
                 {
-                    app.rfe.post_message(msg.clone());
+                    push_unique(&mut self.routes.entry(sub.msg).or_default().connectors, i);
                 }
             }
         }
 
-        // send message to connectors
-        for connector_state in &mut self.connectors {
-            let mut to_send = Vec::new();
-            for msg in &msgs {
-                if connector_state
-                    .subscriptions
-                    .contains(&TargetMsg::new(self.instance, msg.msg.kind()))
-                    || connector_state
-                        .subscriptions
-                        .contains(&TargetMsg::new(Instance::All, msg.msg.kind()))
-                    || connector_state
-                        .subscriptions
-                        .contains(&TargetMsg::new(Instance::Other, msg.msg.kind()))
-                {
-                    to_send.push(msg.clone());
+        #[cfg(feature = "std")]
+        if let Some(group) = &self.group {
+            group.send_subs(GroupSubs {
+                apps: self
+                    .app_list
+                    .iter()
+                    .flat_map(|x| x.rfe.subscriptions.keys().copied())
+                    .collect(),
+                connectors: self
+                    .connectors
+                    .iter()
+                    .flat_map(|x| &x.subscriptions)
+                    .filter(|x| {
+                        x.instance == self.instance
+                            || x.instance == Instance::All
+                            || x.instance == Instance::Other
+                    })
+                    .copied()
+                    .collect(),
+            });
+        }
+    }
+
+    /// Scheduler tick period in microseconds
+    pub fn get_tick_period(&self) -> u64 {
+        1_000_000 / self.tick_rate as u64
+    }
+
+    /// Expected to be called at the tick rate, AsyncApps are polled until their run completes
+    pub fn run(&mut self) {
+        block_on(self.run_async());
+    }
+
+    /// Runs ticks back to back without waiting for the tick period,
+    /// with a SimTimeDriver this runs faster than real time
+    pub fn step(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.run();
+        }
+    }
+
+    pub fn get_sch_counter(&self) -> u64 {
+        self.sch_counter
+    }
+
+    /// Expected to be awaited at the tick rate, AsyncApps are awaited one after the other
+    pub async fn run_async(&mut self) {
+        let mut msgs = core::mem::take(&mut self.msgs_to_send);
+        let time = self.time.clone();
+        time.borrow_mut().time_data.sch_counter = self.sch_counter;
+        let met_time = || time.borrow().met_time();
+        let frame_start = met_time();
+        let mut overruns = Vec::new();
+        for app in self.app_list.iter_mut() {
+            if app.state == AppState::Stopped {
+                continue;
+            }
+            let run_due = app
+                .app_rate
+                .is_due(self.tick_rate, self.sch_counter, app.phase);
+            let hk_due = app
+                .hk_rate
+                .is_due(self.tick_rate, self.sch_counter, app.phase);
+            let out_data_due =
+                app.out_data_rate
+                    .is_due(self.tick_rate, self.sch_counter, app.phase);
+            if !run_due && !hk_due && !out_data_due {
+                continue;
+            }
+            if core::mem::take(&mut app.skip_next) {
+                continue;
+            }
+
+            let cycle_start = met_time();
+            if run_due {
+                let start = met_time();
+                let is_async = match app.app.run_async(&mut app.rfe) {
+                    Some(future) => {
+                        future.await;
+                        true
+                    }
+                    None => false,
+                };
+                if !is_async {
+                    app.app.run(&mut app.rfe);
                 }
+                app.hk.run_time = (met_time() - start) as u32;
+                app.hk.run_count += 1;
+            }
+
+            if hk_due {
+                let start = met_time();
+                app.app.hk(&mut app.rfe);
+                app.hk.hk_time = (met_time() - start) as u32;
+            }
+
+            if out_data_due {
+                let start = met_time();
+                app.app.out_data(&mut app.rfe);
+                app.hk.out_data_time = (met_time() - start) as u32;
             }
-            if to_send.len() > 0 {
-                connector_state.connector.send(to_send);
+            let cycle_time = (met_time() - cycle_start) as u32;
+            app.hk.max_cycle_time = app.hk.max_cycle_time.max(cycle_time);
+            if let Some(overrun) = app.check_budget(cycle_time) {
+                overruns.push(overrun);
             }
+
+            let new_msgs = core::mem::take(&mut app.rfe.msgs_to_send);
+            msgs.extend(new_msgs);
         }
 
+        let msgs = msgs.into_iter().map(Rc::new).collect::<Vec<_>>();
+        let mut instance_cmds = msgs
+            .iter()
+            .filter(|x| self.is_instance_cmd(x))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.update_routes();
+        self.route_msgs(&msgs);
+        self.forward_to_groups(&msgs, true);
         drop(msgs);
         let mut connector_msgs = Vec::new();
 
         // receive messages from connectors
         for connector_state in &mut self.connectors {
             if let Some(msgs) = connector_state.connector.recv() {
+                connector_state.hk.msgs_received += msgs.len() as u32;
                 // check for sublist/sub request
                 for msg in &msgs {
                     if let Msg::SubList(list) = &msg.msg {
                         connector_state.subs_received = true;
                         connector_state.subscriptions.clear();
                         connector_state.subscriptions.extend(list.subs.clone());
+                        self.routes_outdated = true;
                     }
                     if let Msg::SetTimeCmd(new_time) = &msg.msg {
                         self.time.borrow_mut().time_data.time_offset = *new_time;
                     }
                     if let Msg::SubRequest = msg.msg {
                         let mut subs = Vec::new();
-                        for app in self.app_list.values() {
-                            subs.extend(app.rfe.subscriptions.clone());
+                        for app in self.app_list.iter() {
+                            subs.extend(app.rfe.subscriptions.keys().copied());
+                        }
+                        #[cfg(feature = "std")]
+                        if let Some(group) = &self.group {
+                            subs.extend(group.get_group_subs());
                         }
-                        let mut sublist = Vec::new();
-                        sublist.push(MsgPacket {
+                        let sublist = MsgPacket {
                             instance: self.instance,
                             msg: Msg::SubList(SubList { subs }),
                             timestamp: 0,
-                        });
-                        connector_state.connector.send(sublist);
+                        };
+                        connector_state.connector.send(&[Rc::new(sublist)]);
                     }
                 }
 
-                connector_msgs.extend(msgs);
+                connector_msgs.extend(msgs.into_iter().map(Rc::new));
             }
         }
 
+        self.forward_to_groups(&connector_msgs, false);
+        let (group_local, group_remote) = self.recv_from_groups();
+        self.route_msgs(&group_local);
+        instance_cmds.extend(
+            group_local
+                .iter()
+                .filter(|x| self.is_instance_cmd(x))
+                .cloned(),
+        );
+        drop(group_local);
+        connector_msgs.extend(group_remote);
+
         // send messages to apps
-        for app in self.app_list.values_mut() {
-            for msg in &connector_msgs {
-                if app
-                    .rfe
-                    .subscriptions
-                    .contains(&TargetMsg::new(msg.instance, msg.msg.kind()))
-                    || app
-                        .rfe
-                        .subscriptions
-                        .contains(&TargetMsg::new(Instance::All, msg.msg.kind()))
-                    || app
-                        .rfe
-                        .subscriptions
-                        .contains(&TargetMsg::new(Instance::Other, msg.msg.kind()))
-                {
-                    app.rfe.post_message(msg.clone());
-                }
+        for msg in &connector_msgs {
+            let Some(route) = self.routes.get(&msg.msg.kind()) else {
+                continue;
+            };
+            let remote = route
+                .remote
+                .iter()
+                .filter(|(instance, _, _)| *instance == msg.instance)
+                .map(|(_, i, policy)| (*i, *policy));
+            for (i, policy) in route.remote_any.iter().copied().chain(remote) {
+                self.app_list[i].rfe.post_shared(msg.clone(), policy);
             }
         }
 
-        // clear connector subs if subs changed
-        for rfe in self.app_list.values_mut() {
-            if rfe.rfe.subs_updated {
-                for connector_state in &mut self.connectors {
-                    connector_state.subs_received = false;
-                }
-                rfe.rfe.subs_updated = false;
-            }
+        instance_cmds.extend(
+            connector_msgs
+                .iter()
+                .filter(|x| self.is_instance_cmd(x))
+                .cloned(),
+        );
+        for cmd in instance_cmds {
+            self.handle_cmd(&cmd);
         }
 
-        // handle connector subscriptions
+        // handle connector subscriptions, re-request every 0.1s until received then every 100s
+        let subs_retry = Rate::Hz10.get_divisor(self.tick_rate);
+        let subs_refresh = Rate::Custom(100_000_000).get_divisor(self.tick_rate);
         for connector_state in &mut self.connectors {
             if (!connector_state.subs_received
-                && self.sch_counter - connector_state.subs_last_requested >= 10)
+                && self.sch_counter - connector_state.subs_last_requested >= subs_retry)
                 || (connector_state.subs_received
-                    && self.sch_counter - connector_state.subs_last_requested >= 10000)
+                    && self.sch_counter - connector_state.subs_last_requested >= subs_refresh)
             {
                 // request subs
-                let mut request = Vec::new();
-                request.push(MsgPacket {
+                let request = MsgPacket {
                     instance: self.instance,
                     msg: Msg::SubRequest,
                     timestamp: 0,
-                });
-                connector_state.connector.send(request);
+                };
+                connector_state.connector.send(&[Rc::new(request)]);
                 connector_state.subs_last_requested = self.sch_counter;
             }
         }
 
+        if Rate::Hz1.is_due(self.tick_rate, self.sch_counter, 0) {
+            self.send_hk();
+        }
+
+        let frame_time = (met_time() - frame_start) as u32;
+        let tick_period = self.get_tick_period() as u32;
+        if frame_time > tick_period {
+            self.sch_overruns += 1;
+            warn!(
+                "frame {} took {frame_time} us, over the {tick_period} us tick period",
+                self.sch_counter
+            );
+            self.send(Msg::DeadlineMiss(DeadlineMiss {
+                sch_counter: self.sch_counter,
+                frame_time,
+                tick_period,
+                overruns,
+            }));
+        }
+
         self.sch_counter += 1;
     }
 
+    /// Passes msgs sent by apps to local subscribers and connectors
+    fn route_msgs(&mut self, msgs: &[Rc<MsgPacket>]) {
+        let mut connector_batches = self
+            .connectors
+            .iter()
+            .map(|_| Vec::new())
+            .collect::<Vec<Vec<Rc<MsgPacket>>>>();
+        for msg in msgs {
+            let Some(route) = self.routes.get(&msg.msg.kind()) else {
+                continue;
+            };
+            for (i, policy) in &route.local {
+                self.app_list[*i].rfe.post_shared(msg.clone(), *policy);
+            }
+            for i in &route.connectors {
+                connector_batches[*i].push(msg.clone());
+            }
+        }
+
+        // send message to connectors
+        for (connector_state, to_send) in self.connectors.iter_mut().zip(connector_batches) {
+            if !to_send.is_empty() {
+                connector_state.hk.msgs_sent += to_send.len() as u32;
+                connector_state.connector.send(&to_send);
+            }
+        }
+    }
+
+    /// Runs every running app one last time then calls shutdown on all apps, their msgs are
+    /// routed and the connectors flushed. The instance shouldn't be run afterwards.
+    pub fn shutdown(&mut self) {
+        info!("shutting down instance {:?}", self.instance);
+        let mut msgs = core::mem::take(&mut self.msgs_to_send);
+        for app in self.app_list.iter_mut() {
+            if app.state == AppState::Running {
+                app.app.run(&mut app.rfe);
+                app.hk.run_count += 1;
+            }
+            msgs.append(&mut app.rfe.msgs_to_send);
+        }
+        self.update_routes();
+        let msgs = msgs.into_iter().map(Rc::new).collect::<Vec<_>>();
+        self.route_msgs(&msgs);
+        self.forward_to_groups(&msgs, true);
+        drop(msgs);
+
+        // msgs from the final run are already queued for the apps
+        let mut msgs = Vec::new();
+        for app in self.app_list.iter_mut() {
+            app.app.shutdown(&mut app.rfe);
+            msgs.append(&mut app.rfe.msgs_to_send);
+        }
+        let msgs = msgs.into_iter().map(Rc::new).collect::<Vec<_>>();
+        self.route_msgs(&msgs);
+        self.forward_to_groups(&msgs, true);
+
+        for connector_state in &mut self.connectors {
+            connector_state.connector.flush();
+        }
+    }
+
+    fn send_hk(&mut self) {
+        let mut hk = RfeInstanceHk {
+            sch_counter: self.sch_counter,
+            sch_overruns: self.sch_overruns,
+            sch_slips: self.sch_slips,
+            cmds: self.cmd_counters,
+            apps: Vec::new(),
+            connectors: Vec::new(),
+            group: String::new(),
+        };
+        #[cfg(feature = "std")]
+        if let Some(group) = &self.group {
+            hk.group = group.name.clone();
+        }
+        for app in self.app_list.iter_mut() {
+            app.hk.app_rate = app.app_rate;
+            app.hk.hk_rate = app.hk_rate;
+            app.hk.out_data_rate = app.out_data_rate;
+            app.hk.queue_depth = app.rfe.msgs_recevied.len() as u32;
+            app.hk.queue_limit = app.rfe.queue_depth as u32;
+            app.hk.queue_high_water = app.rfe.queue_high_water;
+            app.hk.queue_dropped = app.rfe.queue_dropped;
+            app.hk.state = app.state;
+            app.hk.time_budget = app.time_budget.unwrap_or(0);
+            app.hk.overrun_response = app.overrun_response;
+            app.hk.cmds = app.rfe.get_cmd_counters();
+            hk.apps.push(app.hk.clone());
+            app.hk.max_cycle_time = 0;
+        }
+        for connector_state in &mut self.connectors {
+            connector_state.hk.subs_received = connector_state.subs_received;
+            hk.connectors.push(connector_state.hk);
+        }
+        self.send(Msg::RfeInstanceHk(hk));
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn set_stop_handle(&mut self, stop: StopHandle) {
+        self.stop = stop;
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn set_group_link(&mut self, group: GroupLink) {
+        self.group = Some(group);
+    }
+
+    /// Used to end start from another thread or a signal handler
+    #[cfg(feature = "std")]
+    pub fn get_stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Runs at the tick rate until the stop handle is triggered, then shuts down.
+    /// On linux SIGINT and SIGTERM trigger the stop handle, a second signal exits immediately.
     #[cfg(feature = "std")]
     pub fn start(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Err(e) = self.stop.register_signals() {
+            warn!("failed to register signal handlers {e}");
+        }
+        self.run_until_stopped();
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn run_until_stopped(&mut self) {
         use core::time::Duration;
         use std::{thread::sleep, time::Instant};
 
-        let mut next_time = Instant::now() + Duration::from_millis(10);
-        loop {
-            sleep(next_time - Instant::now());
+        let period = Duration::from_micros(self.get_tick_period());
+        let mut next_time = Instant::now() + period;
+        while !self.stop.is_stopped() {
+            sleep(next_time.saturating_duration_since(Instant::now()));
             self.run();
-            next_time += Duration::from_millis(10);
-            if next_time < Instant::now() {
-                next_time = Instant::now() + Duration::from_millis(10);
+            next_time += period;
+            let now = Instant::now();
+            if next_time < now {
+                let behind = now - next_time;
+                warn!(
+                    "fell {} us behind the tick rate, skipping ahead",
+                    behind.as_micros()
+                );
+                self.sch_slips += 1;
+                next_time = now + period;
             }
         }
+        self.shutdown();
+    }
+}
+
+/// Stops RfeInstance::start, can be sent to other threads
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone)]
+pub struct StopHandle(alloc::sync::Arc<core::sync::atomic::AtomicBool>);
+
+#[cfg(feature = "std")]
+impl StopHandle {
+    pub fn stop(&self) {
+        self.0.store(true, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// SIGINT and SIGTERM call stop, if already stopped the process exits
+    #[cfg(target_os = "linux")]
+    pub fn register_signals(&self) -> Result<()> {
+        use signal_hook::{consts::TERM_SIGNALS, flag};
+        for signal in TERM_SIGNALS {
+            flag::register_conditional_shutdown(*signal, 1, self.0.clone())
+                .map_err(|e| anyhow!("{e}"))?;
+            flag::register(*signal, self.0.clone()).map_err(|e| anyhow!("{e}"))?;
+        }
+        Ok(())
+    }
+}
+
+fn push_unique<T: PartialEq>(list: &mut Vec<T>, value: T) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+/// Subscribers are unique per app, the first subscription's policy is used
+/// Apps are added once with the strictest policy they subscribed with
+fn add_subscriber(list: &mut Vec<(usize, OverflowPolicy)>, app: usize, policy: OverflowPolicy) {
+    match list.iter_mut().find(|x| x.0 == app) {
+        Some(x) => x.1 = x.1.max(policy),
+        None => list.push((app, policy)),
     }
 }
@@ -1,14 +1,208 @@
+extern crate alloc;
+use alloc::{collections::VecDeque, rc::Rc, vec::Vec};
 use anyhow::Result;
+use core::{cell::RefCell, fmt::Debug};
+use log::*;
+
+use crate::{
+    connector::Connector,
+    framing::{encode_msgs, Encoding, FrameDecoder},
+    msg::MsgPacket,
+};
 
 pub trait SerialPort {
     fn open(&mut self) -> Result<()>;
+    /// Returns Ok(0) when no bytes can be written right now
     fn write(&mut self, bytes: &[u8]) -> Result<usize>;
+    /// Returns Ok(0) when no bytes are available
     fn read(&mut self, bytes: &mut [u8]) -> Result<usize>;
 }
 
+/// Calls to send/recv to wait before trying to reopen a failed port
+const REOPEN_INTERVAL: u32 = 100;
+/// Framed bytes waiting on a slow port are dropped past this size
+const MAX_SEND_BUF: usize = 16 * 1024;
+/// Writes flush tries before giving up on a port that isn't accepting bytes
+const FLUSH_ATTEMPTS: u32 = 1000;
+
+/// Sends batches of packets over a serial port as crc checked frames
+pub struct SerialConnector<P: SerialPort> {
+    port: P,
+    is_open: bool,
+    reopen_countdown: u32,
+    decoder: FrameDecoder,
+    send_buf: Vec<u8>,
+    encoding: Encoding,
+}
+
+impl<P: SerialPort> Debug for SerialConnector<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SerialConnector")
+            .field("is_open", &self.is_open)
+            .field("decoder", &self.decoder)
+            .field("send_buf", &self.send_buf.len())
+            .finish()
+    }
+}
+
+impl<P: SerialPort> SerialConnector<P> {
+    /// The port is opened on first use
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            is_open: false,
+            reopen_countdown: 0,
+            decoder: FrameDecoder::new(),
+            send_buf: Vec::new(),
+            encoding: Encoding::Bincode,
+        }
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn get_decoder(&self) -> &FrameDecoder {
+        &self.decoder
+    }
+
+    fn ensure_open(&mut self) -> bool {
+        if self.is_open {
+            return true;
+        }
+        if self.reopen_countdown > 0 {
+            self.reopen_countdown -= 1;
+            return false;
+        }
+        match self.port.open() {
+            Ok(_) => {
+                info!("serial port opened");
+                self.is_open = true;
+            }
+            Err(e) => {
+                warn!("failed to open serial port {e}");
+                self.reopen_countdown = REOPEN_INTERVAL;
+            }
+        }
+        self.is_open
+    }
+
+    fn close(&mut self) {
+        self.is_open = false;
+        self.reopen_countdown = REOPEN_INTERVAL;
+        // a partially written frame is skipped by the remote's resync
+        self.send_buf.clear();
+    }
+
+    fn flush_send_buf(&mut self) {
+        while !self.send_buf.is_empty() {
+            match self.port.write(&self.send_buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    self.send_buf.drain(..n);
+                }
+                Err(e) => {
+                    warn!("serial write error {e}");
+                    self.close();
+                }
+            }
+        }
+    }
+}
+
+impl<P: SerialPort> Connector for SerialConnector<P> {
+    fn send(&mut self, msgs: &[Rc<MsgPacket>]) {
+        if !self.ensure_open() {
+            return;
+        }
+        if self.send_buf.len() > MAX_SEND_BUF {
+            warn!("serial send buffer full, dropping {} msgs", msgs.len());
+        } else {
+            if let Err(e) = encode_msgs(msgs, &mut self.encoding, &mut self.send_buf) {
+                error!("serial send {e}");
+            }
+        }
+        self.flush_send_buf();
+    }
+
+    fn recv(&mut self) -> Option<Vec<MsgPacket>> {
+        if self.ensure_open() {
+            let mut read_buf = [0_u8; 256];
+            loop {
+                match self.port.read(&mut read_buf) {
+                    Ok(0) => break,
+                    Ok(a) => self.decoder.push(&read_buf[0..a]),
+                    Err(e) => {
+                        warn!("serial read error {e}");
+                        self.close();
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.decoder.next_msgs(&mut self.encoding)
+    }
+
+    fn flush(&mut self) {
+        let mut attempts = 0;
+        while self.is_open && !self.send_buf.is_empty() && attempts < FLUSH_ATTEMPTS {
+            self.flush_send_buf();
+            attempts += 1;
+        }
+        if !self.send_buf.is_empty() {
+            warn!(
+                "serial flush gave up, {} bytes not sent",
+                self.send_buf.len()
+            );
+        }
+    }
+}
+
+/// In memory serial port, bytes written to one end are read from the other
+#[derive(Debug)]
+pub struct MemSerialPort {
+    tx: Rc<RefCell<VecDeque<u8>>>,
+    rx: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl MemSerialPort {
+    pub fn new() -> (Self, Self) {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+        (
+            Self {
+                tx: a.clone(),
+                rx: b.clone(),
+            },
+            Self { tx: b, rx: a },
+        )
+    }
+}
+
+impl SerialPort for MemSerialPort {
+    fn open(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<usize> {
+        self.tx.borrow_mut().extend(bytes);
+        Ok(bytes.len())
+    }
+
+    fn read(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        let mut rx = self.rx.borrow_mut();
+        let n = bytes.len().min(rx.len());
+        for (b, r) in bytes.iter_mut().zip(rx.drain(..n)) {
+            *b = r;
+        }
+        Ok(n)
+    }
+}
+
 #[cfg(feature = "std")]
 mod serial_std {
-    use std::io::{Read, Write};
+    use std::io::{ErrorKind, Read, Write};
 
     use anyhow::anyhow;
     use mio_serial::{SerialPortBuilder, SerialPortBuilderExt, SerialStream};
@@ -34,19 +228,27 @@ mod serial_std {
         }
 
         fn write(&mut self, bytes: &[u8]) -> anyhow::Result<usize> {
-            Ok(self
+            match self
                 .serial
                 .as_mut()
                 .ok_or(anyhow!("port not open"))?
-                .write(bytes)?)
+                .write(bytes)
+            {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(0),
+                r => Ok(r?),
+            }
         }
 
         fn read(&mut self, bytes: &mut [u8]) -> anyhow::Result<usize> {
-            Ok(self
+            match self
                 .serial
                 .as_mut()
                 .ok_or(anyhow!("port not open"))?
-                .read(bytes)?)
+                .read(bytes)
+            {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(0),
+                r => Ok(r?),
+            }
         }
     }
 }
@@ -1,10 +1,16 @@
+use core::cell::Cell;
+use critical_section::Mutex;
+
 /// Microseconds timestamp
 pub type Timestamp = u64;
 
+/// Written by the RfeInstance at the start of every tick
 #[derive(Debug, Clone, Copy)]
 pub struct TimeData {
     pub sch_counter: u64,
     pub time_offset: Timestamp,
+    /// microseconds
+    pub tick_period: Timestamp,
 }
 
 pub trait TimeDriver {
@@ -52,11 +58,64 @@ impl SchTimeDriver {
 
 impl TimeDriver for SchTimeDriver {
     fn get_system_time(&self, time_data: TimeData) -> Timestamp {
-        time_data.sch_counter + time_data.time_offset
+        self.get_monotonic_time(time_data) + time_data.time_offset
+    }
+
+    fn get_monotonic_time(&self, time_data: TimeData) -> Timestamp {
+        time_data.sch_counter * time_data.tick_period
+    }
+}
+
+/// Scheduler driven time that can also be advanced by hand, for deterministic tests.
+///
+/// Time only moves with the ticks run and advance, so RfeInstance::step can run
+/// an instance faster than real time.
+pub struct SimTimeDriver {
+    epoch: Timestamp,
+    /// time advanced between or within ticks
+    advanced: Mutex<Cell<Timestamp>>,
+}
+
+impl SimTimeDriver {
+    pub const fn new() -> Self {
+        Self::new_with_epoch(0)
+    }
+
+    /// epoch is the system time at tick 0
+    pub const fn new_with_epoch(epoch: Timestamp) -> Self {
+        Self {
+            epoch,
+            advanced: Mutex::new(Cell::new(0)),
+        }
+    }
+
+    /// Moves time forward without a tick, such as to simulate an app taking time to run
+    pub fn advance(&self, time: Timestamp) {
+        critical_section::with(|cs| {
+            let advanced = self.advanced.borrow(cs);
+            advanced.set(advanced.get() + time);
+        });
+    }
+
+    /// Total time passed to advance
+    pub fn get_advanced(&self) -> Timestamp {
+        critical_section::with(|cs| self.advanced.borrow(cs).get())
+    }
+}
+
+impl Default for SimTimeDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeDriver for SimTimeDriver {
+    fn get_system_time(&self, time_data: TimeData) -> Timestamp {
+        self.epoch + self.get_monotonic_time(time_data) + time_data.time_offset
     }
 
     fn get_monotonic_time(&self, time_data: TimeData) -> Timestamp {
-        return time_data.sch_counter * 10000;
+        time_data.sch_counter * time_data.tick_period + self.get_advanced()
     }
 }
 
@@ -0,0 +1,255 @@
+//! Runs AsyncApps on a simple single threaded executor
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+use anyhow::Result;
+use rfe::{msg::*, time::SchTimeDriver, App, AsyncApp, Rate, Rfe, RfeInstance};
+
+type Task<'a> = Pin<Box<dyn Future<Output = ()> + 'a>>;
+
+/// Polls every task in turn until all complete
+fn run_tasks(mut tasks: Vec<Task<'_>>) {
+    let mut cx = Context::from_waker(Waker::noop());
+    while !tasks.is_empty() {
+        tasks.retain_mut(|task| task.as_mut().poll(&mut cx).is_pending());
+    }
+}
+
+/// Pending on the first poll
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            // ready to be polled again right away
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+fn yield_now() -> YieldNow {
+    YieldNow(false)
+}
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+/// Stands in for an I2C bus, reads wait until the device task has written a byte
+#[derive(Default, Clone)]
+struct Bus(Rc<RefCell<VecDeque<u8>>>);
+
+impl Bus {
+    async fn read(&self) -> u8 {
+        loop {
+            if let Some(byte) = self.0.borrow_mut().pop_front() {
+                return byte;
+            }
+            yield_now().await;
+        }
+    }
+}
+
+struct SensorApp {
+    bus: Bus,
+    log: Log,
+    rate: Rate,
+    /// polls to wait before reading, like a conversion time
+    settle_polls: u32,
+}
+
+impl AsyncApp for SensorApp {
+    fn init(&mut self, _rfe: &mut Rfe) -> Result<()> {
+        Ok(())
+    }
+
+    async fn run(&mut self, rfe: &mut Rfe<'_>) {
+        self.log.borrow_mut().push("sensor start".into());
+        for _ in 0..self.settle_polls {
+            yield_now().await;
+        }
+        let value = self.bus.read().await;
+        self.log.borrow_mut().push(format!("sensor read {value}"));
+        rfe.send(Msg::ExampleOutData(ExampleOutData {
+            counter: value as u32,
+        }));
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        self.rate
+    }
+}
+
+struct SinkApp {
+    name: &'static str,
+    log: Log,
+    received: Vec<u32>,
+}
+
+impl App for SinkApp {
+    fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
+        rfe.subscribe(TargetMsg::new(Instance::Example, MsgKind::ExampleOutData));
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        self.log.borrow_mut().push(format!("{} run", self.name));
+        while let Some(msg) = rfe.recv() {
+            if let Msg::ExampleOutData(data) = msg.msg {
+                self.received.push(data.counter);
+            }
+        }
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+#[test]
+fn other_tasks_run_while_app_awaits() {
+    let time_driver = SchTimeDriver::new();
+    let log = Log::default();
+    let bus = Bus::default();
+    let mut first = SinkApp {
+        name: "first",
+        log: log.clone(),
+        received: Vec::new(),
+    };
+    let mut sensor = SensorApp {
+        bus: bus.clone(),
+        log: log.clone(),
+        rate: Rate::Hz100,
+        settle_polls: 0,
+    };
+    let mut last = SinkApp {
+        name: "last",
+        log: log.clone(),
+        received: Vec::new(),
+    };
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("first", &mut first).unwrap();
+        instance.add_app("sensor", &mut sensor).unwrap();
+        instance.add_app("last", &mut last).unwrap();
+
+        let instance_task = async {
+            for _ in 0..3 {
+                instance.run_async().await;
+            }
+        };
+        let device_log = log.clone();
+        let device_task = async move {
+            for value in 1..=3 {
+                // the sensor waits a few polls for each byte
+                for _ in 0..5 {
+                    yield_now().await;
+                }
+                device_log
+                    .borrow_mut()
+                    .push(format!("device write {value}"));
+                bus.0.borrow_mut().push_back(value);
+            }
+        };
+        run_tasks(vec![Box::pin(instance_task), Box::pin(device_task)]);
+    }
+
+    let log = log.borrow();
+    let tick = |value: u8| {
+        [
+            "first run".to_string(),
+            "sensor start".to_string(),
+            format!("device write {value}"),
+            format!("sensor read {value}"),
+            "last run".to_string(),
+        ]
+    };
+    let expected = (1..=3).flat_map(tick).collect::<Vec<_>>();
+    assert_eq!(*log, expected);
+
+    // msgs are routed at the end of the tick
+    assert_eq!(first.received, vec![1, 2]);
+    assert_eq!(last.received, vec![1, 2]);
+}
+
+#[test]
+fn async_apps_keep_their_rate() {
+    let time_driver = SchTimeDriver::new();
+    let log = Log::default();
+    let bus = Bus::default();
+    bus.0.borrow_mut().extend(0..10);
+    let mut sensor = SensorApp {
+        bus: bus.clone(),
+        log: log.clone(),
+        rate: Rate::Hz10,
+        settle_polls: 0,
+    };
+    let mut sink = SinkApp {
+        name: "sink",
+        log: log.clone(),
+        received: Vec::new(),
+    };
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("sensor", &mut sensor).unwrap();
+        instance.add_app("sink", &mut sink).unwrap();
+        run_tasks(vec![Box::pin(async {
+            for _ in 0..100 {
+                instance.run_async().await;
+            }
+        })]);
+    }
+
+    let log = log.borrow();
+    assert_eq!(log.iter().filter(|x| *x == "sensor start").count(), 10);
+    assert_eq!(log.iter().filter(|x| *x == "sink run").count(), 100);
+    assert_eq!(sink.received, (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn run_polls_async_apps_to_completion() {
+    let time_driver = SchTimeDriver::new();
+    let log = Log::default();
+    let mut sensor = SensorApp {
+        bus: Bus::default(),
+        log: log.clone(),
+        rate: Rate::Hz100,
+        settle_polls: 3,
+    };
+    sensor.bus.0.borrow_mut().extend([7, 8, 9]);
+    let mut sink = SinkApp {
+        name: "sink",
+        log: log.clone(),
+        received: Vec::new(),
+    };
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("sensor", &mut sensor).unwrap();
+        instance.add_app("sink", &mut sink).unwrap();
+        for _ in 0..3 {
+            instance.run();
+        }
+    }
+
+    // msgs are routed at the end of the tick, 9 is still queued
+    assert_eq!(sink.received, vec![7, 8]);
+}
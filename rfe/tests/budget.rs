@@ -0,0 +1,119 @@
+//! App time budgets, overrun responses and deadline misses on simulated time
+
+mod common;
+
+use std::{cell::Cell, rc::Rc};
+
+use anyhow::Result;
+use common::*;
+use rfe::{msg::*, time::SimTimeDriver, App, Rate, Rfe, RfeInstance};
+
+/// Takes cost microseconds of simulated time every run
+struct SlowApp<'a> {
+    time_driver: &'a SimTimeDriver,
+    cost: Rc<Cell<u64>>,
+    runs: Rc<Cell<u32>>,
+}
+
+impl<'a> SlowApp<'a> {
+    fn new(time_driver: &'a SimTimeDriver, cost: u64) -> Self {
+        Self {
+            time_driver,
+            cost: Rc::new(Cell::new(cost)),
+            runs: Rc::default(),
+        }
+    }
+}
+
+impl App for SlowApp<'_> {
+    fn init(&mut self, _rfe: &mut Rfe) -> Result<()> {
+        Ok(())
+    }
+
+    fn run(&mut self, _rfe: &mut Rfe) {
+        self.runs.set(self.runs.get() + 1);
+        self.time_driver.advance(self.cost.get());
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+/// Returns how often the slow app ran in 6 ticks with a 5 ms budget
+fn run_over_budget(cost: u64, response: OverrunResponse) -> u32 {
+    let time_driver = SimTimeDriver::new();
+    let mut slow = SlowApp::new(&time_driver, cost);
+    let runs = slow.runs.clone();
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("slow", &mut slow).unwrap();
+        instance
+            .set_time_budget("slow", Some(5_000), response)
+            .unwrap();
+        assert!(instance
+            .set_time_budget("missing", Some(5_000), response)
+            .is_err());
+        instance.step(6);
+    }
+    runs.get()
+}
+
+#[test]
+fn overrun_responses() {
+    assert_eq!(run_over_budget(4_000, OverrunResponse::Stop), 6);
+    assert_eq!(run_over_budget(6_000, OverrunResponse::Log), 6);
+    // every other tick is skipped
+    assert_eq!(run_over_budget(6_000, OverrunResponse::SkipNext), 3);
+    assert_eq!(run_over_budget(6_000, OverrunResponse::Stop), 1);
+}
+
+#[test]
+fn reports_deadline_misses_with_the_apps_over_budget() {
+    let time_driver = SimTimeDriver::new();
+    let mut slow = SlowApp::new(&time_driver, 8_000);
+    let mut fast = SlowApp::new(&time_driver, 3_000);
+    let mut monitor = Recorder::new(&[MsgKind::DeadlineMiss]);
+    let (msgs, fast_cost) = (monitor.msgs.clone(), fast.cost.clone());
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("slow", &mut slow).unwrap();
+        instance.add_app("fast", &mut fast).unwrap();
+        instance.add_app("monitor", &mut monitor).unwrap();
+        instance
+            .set_time_budget("slow", Some(5_000), OverrunResponse::Log)
+            .unwrap();
+        instance
+            .set_time_budget("fast", Some(5_000), OverrunResponse::Log)
+            .unwrap();
+        instance.step(1);
+        // within the tick period the overrun alone isn't a deadline miss
+        fast_cost.set(0);
+        instance.step(2);
+    }
+    let misses = msgs
+        .borrow()
+        .iter()
+        .filter_map(|x| match &x.msg {
+            Msg::DeadlineMiss(miss) => Some(miss.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        misses,
+        vec![DeadlineMiss {
+            sch_counter: 0,
+            frame_time: 11_000,
+            tick_period: 10_000,
+            overruns: vec![AppOverrun {
+                name: "slow".into(),
+                cycle_time: 8_000,
+                time_budget: 5_000,
+            }],
+        }]
+    );
+}
@@ -0,0 +1,33 @@
+//! Space packet APIDs
+
+use rfe::{ccsds::*, msg::*};
+
+#[test]
+fn every_msg_kind_fits_the_apid() {
+    // the APID holds 7 bits of the msg kind
+    let kinds = MsgKind::to_vec();
+    assert!(
+        kinds.len() <= 128,
+        "{} msg kinds don't fit the APID",
+        kinds.len()
+    );
+    for kind in kinds {
+        let apid = get_apid(Instance::Example, kind);
+        assert!(apid < 1 << 11);
+        assert_eq!(from_apid(apid), Some((Instance::Example, kind)));
+    }
+}
+
+#[test]
+fn space_packets_round_trip() {
+    let msg = MsgPacket {
+        instance: Instance::Example,
+        msg: Msg::ExampleOutData(ExampleOutData { counter: 7 }),
+        timestamp: 1_000,
+    };
+    let mut codec = CcsdsCodec::new();
+    let mut buf = Vec::new();
+    codec.encode_packet(&msg, &mut buf);
+    codec.encode_packet(&msg, &mut buf);
+    assert_eq!(codec.decode(&buf), vec![msg.clone(), msg]);
+}
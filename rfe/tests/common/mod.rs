@@ -0,0 +1,160 @@
+//! Apps and a connector recording what an RfeInstance passes them
+// each test binary uses a different part of it
+#![allow(dead_code)]
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use anyhow::Result;
+use rfe::{connector::Connector, msg::*, App, Rate, Rfe};
+
+/// Sends ExampleOutData with an increasing counter every run
+#[derive(Default)]
+pub struct CounterApp {
+    counter: u32,
+}
+
+impl App for CounterApp {
+    fn init(&mut self, _rfe: &mut Rfe) -> Result<()> {
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        rfe.send(Msg::ExampleOutData(ExampleOutData {
+            counter: self.counter,
+        }));
+        self.counter += 1;
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+/// Records the msgs it subscribed to and how often it was called.
+/// Msgs pushed to to_send are sent on its next run, shutdown_msgs when it shuts down.
+#[derive(Default)]
+pub struct Recorder {
+    subs: Vec<TargetMsg>,
+    pub msgs: Rc<RefCell<Vec<MsgPacket>>>,
+    pub runs: Rc<Cell<u32>>,
+    pub shutdowns: Rc<Cell<u32>>,
+    pub to_send: Rc<RefCell<Vec<Msg>>>,
+    pub shutdown_msgs: Vec<Msg>,
+}
+
+impl Recorder {
+    pub fn new(kinds: &[MsgKind]) -> Self {
+        Self {
+            subs: kinds
+                .iter()
+                .map(|x| TargetMsg::new(Instance::All, *x))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl App for Recorder {
+    fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
+        rfe.subscribe_all(self.subs.iter().copied());
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        self.runs.set(self.runs.get() + 1);
+        while let Some(msg) = rfe.recv() {
+            self.msgs.borrow_mut().push((*msg).clone());
+        }
+        for msg in self.to_send.borrow_mut().drain(..) {
+            rfe.send(msg);
+        }
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+
+    fn shutdown(&mut self, rfe: &mut Rfe) {
+        self.shutdowns.set(self.shutdowns.get() + 1);
+        for msg in self.shutdown_msgs.drain(..) {
+            rfe.send(msg);
+        }
+    }
+}
+
+/// Records the msgs sent to it, answers subscription requests with its subs
+#[derive(Debug, Default)]
+pub struct RecordingConnector {
+    subs: Vec<TargetMsg>,
+    requested: bool,
+    pub sent: Rc<RefCell<Vec<MsgPacket>>>,
+    pub flushes: Rc<Cell<u32>>,
+}
+
+impl RecordingConnector {
+    pub fn new(kinds: &[MsgKind]) -> Self {
+        Self {
+            subs: kinds
+                .iter()
+                .map(|x| TargetMsg::new(Instance::All, *x))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Connector for RecordingConnector {
+    fn send(&mut self, msgs: &[Rc<MsgPacket>]) {
+        for msg in msgs {
+            if msg.msg == Msg::SubRequest {
+                self.requested = true;
+            } else {
+                self.sent.borrow_mut().push((**msg).clone());
+            }
+        }
+    }
+
+    fn recv(&mut self) -> Option<Vec<MsgPacket>> {
+        if !core::mem::take(&mut self.requested) {
+            return None;
+        }
+        let list = SubList {
+            subs: self.subs.clone(),
+        };
+        Some(vec![MsgPacket::new(Instance::None, Msg::SubList(list), 0)])
+    }
+
+    fn flush(&mut self) {
+        self.flushes.set(self.flushes.get() + 1);
+    }
+}
+
+/// Counters of the ExampleOutData msgs
+pub fn counters(msgs: &[MsgPacket]) -> Vec<u32> {
+    msgs.iter()
+        .filter_map(|x| match &x.msg {
+            Msg::ExampleOutData(data) => Some(data.counter),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn cmd_statuses(msgs: &[MsgPacket]) -> Vec<(MsgKind, CmdState, CmdError)> {
+    msgs.iter()
+        .filter_map(|x| match &x.msg {
+            Msg::CmdStatus(status) => Some((status.cmd, status.state, status.error)),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,165 @@
+//! Log records sent as Event msgs by an EventService on simulated time
+
+use std::{cell::RefCell, rc::Rc};
+
+use anyhow::Result;
+use log::{Level, LevelFilter, Log, Record};
+use rfe::{
+    event::{EventLogger, EventService},
+    msg::*,
+    time::SimTimeDriver,
+    App, Rate, Rfe, RfeInstance,
+};
+
+/// Collects every Event msg
+#[derive(Default)]
+struct Monitor {
+    events: Rc<RefCell<Vec<Event>>>,
+}
+
+impl App for Monitor {
+    fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
+        rfe.subscribe(TargetMsg::new(Instance::All, MsgKind::Event));
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        while let Some(msg) = rfe.recv() {
+            if let Msg::Event(event) = &msg.msg {
+                self.events.borrow_mut().push(event.clone());
+            }
+        }
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+fn log(logger: &EventLogger, target: &str, level: Level, text: &str) {
+    logger.log(
+        &Record::builder()
+            .args(format_args!("{text}"))
+            .level(level)
+            .target(target)
+            .build(),
+    );
+}
+
+fn texts(events: &[Event]) -> Vec<(&str, &str)> {
+    events
+        .iter()
+        .map(|x| (x.source.as_str(), x.text.as_str()))
+        .collect()
+}
+
+#[test]
+fn filters_levels_per_source() {
+    static LOGGER: EventLogger = EventLogger::new(16);
+    let time_driver = SimTimeDriver::new();
+    let mut service = EventService::new(&LOGGER);
+    let mut monitor = Monitor::default();
+    let events = monitor.events.clone();
+
+    LOGGER.set_filter("noisy", LevelFilter::Warn);
+    service
+        .handle_cmd(&EventFilterCmd {
+            source: "verbose".into(),
+            filter: EventFilter::Debug,
+        })
+        .unwrap();
+    log(&LOGGER, "noisy::camera", Level::Info, "dropped");
+    log(&LOGGER, "noisy::camera", Level::Warn, "noisy warn");
+    log(&LOGGER, "quiet", Level::Debug, "dropped");
+    log(&LOGGER, "quiet", Level::Info, "quiet info");
+    log(&LOGGER, "verbose", Level::Trace, "verbose trace");
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("events", &mut service).unwrap();
+        instance.add_app("monitor", &mut monitor).unwrap();
+        instance.step(2);
+    }
+    let events = events.borrow();
+    assert_eq!(
+        texts(&events),
+        vec![
+            ("noisy", "noisy warn"),
+            ("quiet", "quiet info"),
+            ("verbose", "verbose trace"),
+        ]
+    );
+    assert_eq!(events[0].severity, EventSeverity::Warn);
+    assert_eq!(events[2].severity, EventSeverity::Debug);
+    assert!(events.iter().all(|x| x.instance == Instance::Example));
+}
+
+#[test]
+fn throttles_floods_and_reports_the_count() {
+    static LOGGER: EventLogger = EventLogger::new(16);
+    let time_driver = SimTimeDriver::new();
+    // 2 events per source every 10 ticks
+    let mut service = EventService::new_with_throttle(&LOGGER, 2, 100_000);
+    let mut monitor = Monitor::default();
+    let events = monitor.events.clone();
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("events", &mut service).unwrap();
+        instance.add_app("monitor", &mut monitor).unwrap();
+        for i in 0..5 {
+            log(&LOGGER, "flood", Level::Info, &format!("flood {i}"));
+        }
+        log(&LOGGER, "flood", Level::Error, "flood error");
+        log(&LOGGER, "calm", Level::Info, "calm");
+        instance.step(5);
+        assert_eq!(
+            texts(&events.borrow()),
+            vec![("flood", "flood 0"), ("flood", "flood 1"), ("calm", "calm")]
+        );
+
+        // the summary is sent once the window ends, then the source can send again
+        instance.step(10);
+        log(&LOGGER, "flood", Level::Info, "flood 5");
+        instance.step(10);
+    }
+    let events = events.borrow();
+    assert_eq!(
+        texts(&events[3..]),
+        vec![("flood", "4 events throttled"), ("flood", "flood 5")]
+    );
+    assert_eq!(events[3].throttled, 4);
+    // the highest severity throttled
+    assert_eq!(events[3].severity, EventSeverity::Error);
+}
+
+#[test]
+fn drops_records_when_the_queue_is_full() {
+    static LOGGER: EventLogger = EventLogger::new(3);
+    let time_driver = SimTimeDriver::new();
+    let mut service = EventService::new(&LOGGER);
+    let mut monitor = Monitor::default();
+    let events = monitor.events.clone();
+    for i in 0..5 {
+        log(&LOGGER, "app", Level::Info, &format!("event {i}"));
+    }
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("events", &mut service).unwrap();
+        instance.add_app("monitor", &mut monitor).unwrap();
+        instance.step(2);
+    }
+    let events = events.borrow();
+    assert_eq!(
+        texts(&events),
+        vec![
+            ("rfe", "2 events dropped, event queue full"),
+            ("app", "event 0"),
+            ("app", "event 1"),
+            ("app", "event 2"),
+        ]
+    );
+    assert_eq!(events[0].severity, EventSeverity::Warn);
+}
@@ -0,0 +1,82 @@
+//! Msgs passed between the thread groups of an Executor
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use rfe::{
+    connector::{Connector, MemConnector},
+    executor::{Executor, ThreadGroup},
+    msg::*,
+    time::SchTimeDriver,
+    App, Rate, Rfe,
+};
+
+/// Sends ExampleOutData every run
+struct SendingApp;
+
+impl App for SendingApp {
+    fn init(&mut self, _rfe: &mut Rfe) -> Result<()> {
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        rfe.send(Msg::ExampleOutData(Default::default()));
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz10
+    }
+}
+
+#[test]
+fn connectors_receive_msgs_of_apps_in_other_groups() {
+    let time_driver = SchTimeDriver::new();
+    let mut app = SendingApp;
+    let (mut connector, mut remote) = MemConnector::new();
+
+    let mut connector_group = ThreadGroup::new("connectors");
+    connector_group.add_connector(&mut connector);
+    let mut app_group = ThreadGroup::new("apps");
+    app_group.add_app("sending", &mut app);
+
+    let mut executor = Executor::new(Instance::Example, &time_driver);
+    executor.add_group(connector_group).unwrap();
+    executor.add_group(app_group).unwrap();
+    let stop = executor.get_stop_handle();
+
+    let received = thread::scope(|s| {
+        let handle = s.spawn(|| executor.start());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut received = 0;
+        while received < 3 && Instant::now() < deadline {
+            let Some(msgs) = remote.recv() else {
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            };
+            for msg in msgs {
+                match msg.msg {
+                    Msg::SubRequest => {
+                        let list = SubList {
+                            subs: vec![TargetMsg::new(Instance::Example, MsgKind::ExampleOutData)],
+                        };
+                        remote
+                            .send(&[MsgPacket::new(Instance::None, Msg::SubList(list), 0).into()]);
+                    }
+                    Msg::ExampleOutData(_) => received += 1,
+                    _ => {}
+                }
+            }
+        }
+        stop.stop();
+        handle.join().unwrap().unwrap();
+        received
+    });
+    assert_eq!(received, 3);
+}
@@ -0,0 +1,120 @@
+//! Frames over a byte stream that splits, merges and corrupts them
+
+use rfe::{ccsds::CcsdsCodec, framing::*, msg::*};
+
+fn event(text_len: usize) -> MsgPacket {
+    MsgPacket::new(
+        Instance::Example,
+        Msg::Event(Event {
+            text: "x".repeat(text_len),
+            ..Default::default()
+        }),
+        7,
+    )
+}
+
+fn counter(counter: u32) -> MsgPacket {
+    MsgPacket::new(
+        Instance::Example,
+        Msg::ExampleOutData(ExampleOutData { counter }),
+        counter as u64,
+    )
+}
+
+fn encode(msgs: &[MsgPacket]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode_msgs(msgs, &mut Encoding::Bincode, &mut buf).unwrap();
+    buf
+}
+
+fn decode_all(decoder: &mut FrameDecoder) -> Vec<MsgPacket> {
+    decoder
+        .next_msgs(&mut Encoding::Bincode)
+        .unwrap_or_default()
+}
+
+#[test]
+fn reassembles_frames_split_across_reads() {
+    let msgs = (0..5).map(counter).collect::<Vec<_>>();
+    let bytes = encode(&msgs);
+    let mut decoder = FrameDecoder::new();
+    let mut received = Vec::new();
+    for b in &bytes {
+        decoder.push(&[*b]);
+        received.extend(decode_all(&mut decoder));
+    }
+    assert_eq!(received, msgs);
+    assert_eq!(decoder.get_pending_bytes(), 0);
+}
+
+#[test]
+fn decodes_frames_coalesced_in_one_read() {
+    let mut bytes = encode(&[counter(1)]);
+    bytes.extend(encode(&[counter(2), counter(3)]));
+    bytes.extend(encode(&[counter(4)]));
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&bytes);
+    assert_eq!(
+        decode_all(&mut decoder),
+        (1..=4).map(counter).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn resyncs_after_garbage() {
+    let mut bytes = vec![0x00, SYNC[0], 0x12, SYNC[0], SYNC[1], 0xFF];
+    let garbage = bytes.len() as u32;
+    bytes.extend(encode(&[counter(1)]));
+    // a frame with a damaged payload between two good ones
+    let mut damaged = encode(&[counter(2)]);
+    damaged[HEADER_SIZE] ^= 0xFF;
+    bytes.extend(&damaged);
+    bytes.extend(encode(&[counter(3)]));
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&bytes);
+    assert_eq!(decode_all(&mut decoder), vec![counter(1), counter(3)]);
+    assert_eq!(decoder.get_crc_errors(), 1);
+    assert_eq!(decoder.get_dropped_bytes(), garbage + damaged.len() as u32);
+}
+
+#[test]
+fn splits_batches_over_the_max_payload() {
+    let msgs = (0..4)
+        .map(|_| event(MAX_PAYLOAD_SIZE / 3))
+        .collect::<Vec<_>>();
+    let bytes = encode(&msgs);
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&bytes);
+    let mut frames = 0;
+    while let Some(payload) = decoder.next_frame() {
+        assert!(payload.len() <= MAX_PAYLOAD_SIZE);
+        frames += 1;
+    }
+    assert_eq!(frames, 2);
+    decoder.push(&bytes);
+    assert_eq!(decode_all(&mut decoder), msgs);
+}
+
+#[test]
+fn drops_msgs_too_large_for_a_frame() {
+    let msgs = vec![counter(1), event(MAX_PAYLOAD_SIZE), counter(2)];
+    for mut encoding in [Encoding::Bincode, Encoding::Ccsds(CcsdsCodec::new())] {
+        let mut bytes = Vec::new();
+        assert!(encode_msgs(&msgs, &mut encoding, &mut bytes).is_err());
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&bytes);
+        assert_eq!(
+            decoder.next_msgs(&mut encoding).unwrap(),
+            vec![counter(1), counter(2)]
+        );
+    }
+
+    // a frame claiming more than the max is noise to the receiver
+    let mut frame = encode(&[counter(3)]);
+    frame[SYNC.len()..HEADER_SIZE].copy_from_slice(&(MAX_PAYLOAD_SIZE as u32 + 1).to_le_bytes());
+    frame.extend(encode(&[counter(4)]));
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&frame);
+    assert_eq!(decode_all(&mut decoder), vec![counter(4)]);
+}
@@ -0,0 +1,113 @@
+//! Stopping, starting and removing apps while the instance runs
+
+mod common;
+
+use common::*;
+use rfe::{msg::*, time::SimTimeDriver, RfeInstance};
+
+#[test]
+fn stopped_apps_keep_or_discard_their_msgs() {
+    let time_driver = SimTimeDriver::new();
+    let mut counter = CounterApp::default();
+    let mut keep = Recorder::new(&[MsgKind::ExampleOutData]);
+    let mut discard = Recorder::new(&[MsgKind::ExampleOutData]);
+    let (keep_msgs, keep_runs) = (keep.msgs.clone(), keep.runs.clone());
+    let discard_msgs = discard.msgs.clone();
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("counter", &mut counter).unwrap();
+        instance.add_app("keep", &mut keep).unwrap();
+        instance.add_app("discard", &mut discard).unwrap();
+        instance.step(2);
+
+        instance.stop_app("keep", StoppedQueuePolicy::Keep).unwrap();
+        instance
+            .stop_app("discard", StoppedQueuePolicy::Discard)
+            .unwrap();
+        assert!(instance.stop_app("keep", StoppedQueuePolicy::Keep).is_err());
+        assert!(instance
+            .stop_app("missing", StoppedQueuePolicy::Keep)
+            .is_err());
+        instance.step(3);
+
+        instance.start_app("keep").unwrap();
+        instance.start_app("discard").unwrap();
+        assert!(instance.start_app("keep").is_err());
+        instance.step(2);
+    }
+    // counters are received the tick after they are sent
+    assert_eq!(counters(&keep_msgs.borrow()), vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(counters(&discard_msgs.borrow()), vec![0, 5]);
+    assert_eq!(keep_runs.get(), 4);
+}
+
+#[test]
+fn removed_apps_are_shut_down_and_left_out_of_the_routes() {
+    let time_driver = SimTimeDriver::new();
+    let mut counter = CounterApp::default();
+    let mut removed = Recorder::new(&[MsgKind::ExampleOutData]);
+    removed.shutdown_msgs = vec![Msg::ExampleHk(Default::default())];
+    let mut other = Recorder::new(&[MsgKind::ExampleHk]);
+    let (removed_msgs, removed_shutdowns) = (removed.msgs.clone(), removed.shutdowns.clone());
+    let other_msgs = other.msgs.clone();
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("counter", &mut counter).unwrap();
+        instance.add_app("removed", &mut removed).unwrap();
+        instance.add_app("other", &mut other).unwrap();
+        instance.step(2);
+        instance.remove_app("removed").unwrap();
+        assert!(instance.remove_app("removed").is_err());
+        instance.step(3);
+    }
+    assert_eq!(counters(&removed_msgs.borrow()), vec![0]);
+    assert_eq!(removed_shutdowns.get(), 1);
+    // other took the removed app's place in the app list but not its subscriptions
+    let other_msgs = other_msgs.borrow();
+    assert_eq!(other_msgs.len(), 1);
+    assert!(matches!(other_msgs[0].msg, Msg::ExampleHk(_)));
+}
+
+#[test]
+fn reports_the_status_of_app_cmds() {
+    let time_driver = SimTimeDriver::new();
+    let mut counter = CounterApp::default();
+    let mut operator = Recorder::new(&[MsgKind::CmdStatus]);
+    let stop = || {
+        Msg::StopApp(StopAppCmd {
+            app_name: "counter".into(),
+            queue_policy: StoppedQueuePolicy::Discard,
+        })
+    };
+    operator.to_send.borrow_mut().extend([
+        stop(),
+        stop(),
+        Msg::StartApp(StartAppCmd {
+            app_name: "counter".into(),
+        }),
+        Msg::RemoveApp(RemoveAppCmd {
+            app_name: "missing".into(),
+        }),
+        Msg::RemoveApp(RemoveAppCmd {
+            app_name: "counter".into(),
+        }),
+    ]);
+    let msgs = operator.msgs.clone();
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("counter", &mut counter).unwrap();
+        instance.add_app("operator", &mut operator).unwrap();
+        instance.step(3);
+        assert!(instance.start_app("counter").is_err());
+    }
+    assert_eq!(
+        cmd_statuses(&msgs.borrow()),
+        vec![
+            (MsgKind::StopApp, CmdState::Executed, CmdError::None),
+            (MsgKind::StopApp, CmdState::Failed, CmdError::InvalidState),
+            (MsgKind::StartApp, CmdState::Executed, CmdError::None),
+            (MsgKind::RemoveApp, CmdState::Failed, CmdError::NotFound),
+            (MsgKind::RemoveApp, CmdState::Executed, CmdError::None),
+        ]
+    );
+}
@@ -0,0 +1,142 @@
+//! App queue overflow policies and drop counts
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use anyhow::Result;
+use rfe::{msg::*, time::SimTimeDriver, App, OverflowPolicy, Rate, Rfe, RfeInstance};
+
+/// Sends a burst of counters on its first run
+struct BurstApp {
+    count: u32,
+    sent: bool,
+}
+
+impl App for BurstApp {
+    fn init(&mut self, _rfe: &mut Rfe) -> Result<()> {
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        if !core::mem::replace(&mut self.sent, true) {
+            for counter in 0..self.count {
+                rfe.send(Msg::ExampleOutData(ExampleOutData { counter }));
+            }
+        }
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+/// Runs after the burst is queued, with a queue of 3
+struct QueueApp {
+    subs: Vec<(Instance, OverflowPolicy)>,
+    received: Rc<RefCell<Vec<u32>>>,
+    dropped: Rc<Cell<u32>>,
+}
+
+impl QueueApp {
+    fn new(subs: &[(Instance, OverflowPolicy)]) -> Self {
+        Self {
+            subs: subs.to_vec(),
+            received: Rc::default(),
+            dropped: Rc::default(),
+        }
+    }
+}
+
+impl App for QueueApp {
+    fn init(&mut self, rfe: &mut Rfe) -> Result<()> {
+        rfe.set_queue_depth(3);
+        for (instance, policy) in &self.subs {
+            rfe.subscribe_with_policy(TargetMsg::new(*instance, MsgKind::ExampleOutData), *policy);
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        while let Some(msg) = rfe.recv() {
+            if let Msg::ExampleOutData(data) = &msg.msg {
+                self.received.borrow_mut().push(data.counter);
+            }
+        }
+        self.dropped.set(rfe.get_queue_dropped());
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz10
+    }
+}
+
+/// Returns the counters received from a burst of 5 and how many were dropped
+fn receive_burst(subs: &[(Instance, OverflowPolicy)]) -> (Vec<u32>, u32) {
+    let time_driver = SimTimeDriver::new();
+    let mut burst = BurstApp {
+        count: 5,
+        sent: false,
+    };
+    let mut queue = QueueApp::new(subs);
+    let (received, dropped) = (queue.received.clone(), queue.dropped.clone());
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("burst", &mut burst).unwrap();
+        instance.add_app("queue", &mut queue).unwrap();
+        instance.step(10);
+    }
+    let received = received.borrow().clone();
+    (received, dropped.get())
+}
+
+#[test]
+fn drop_oldest_keeps_the_newest_msgs() {
+    let (received, dropped) = receive_burst(&[(Instance::Example, OverflowPolicy::DropOldest)]);
+    assert_eq!(received, vec![2, 3, 4]);
+    assert_eq!(dropped, 2);
+}
+
+#[test]
+fn drop_newest_keeps_the_oldest_msgs() {
+    let (received, dropped) = receive_burst(&[(Instance::Example, OverflowPolicy::DropNewest)]);
+    assert_eq!(received, vec![0, 1, 2]);
+    assert_eq!(dropped, 2);
+}
+
+#[test]
+fn keep_latest_counts_replaced_msgs() {
+    let (received, dropped) = receive_burst(&[(Instance::Example, OverflowPolicy::KeepLatest)]);
+    assert_eq!(received, vec![4]);
+    assert_eq!(dropped, 4);
+}
+
+#[test]
+fn uses_the_strictest_policy_of_a_msg() {
+    for subs in [
+        [
+            (Instance::All, OverflowPolicy::DropNewest),
+            (Instance::Example, OverflowPolicy::KeepLatest),
+        ],
+        [
+            (Instance::All, OverflowPolicy::KeepLatest),
+            (Instance::Example, OverflowPolicy::DropOldest),
+        ],
+    ] {
+        assert_eq!(receive_burst(&subs), (vec![4], 4));
+    }
+    let (received, _) = receive_burst(&[
+        (Instance::All, OverflowPolicy::DropOldest),
+        (Instance::Example, OverflowPolicy::DropNewest),
+    ]);
+    assert_eq!(received, vec![0, 1, 2]);
+}
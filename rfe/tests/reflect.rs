@@ -0,0 +1,73 @@
+//! Csv columns of msg packets
+
+use rfe::msg::*;
+
+fn columns(msg: Msg) -> Vec<(String, String)> {
+    MsgPacket::new(Instance::Example, msg, 42).to_csv()
+}
+
+fn names(columns: &[(String, String)]) -> Vec<&str> {
+    columns.iter().map(|x| x.0.as_str()).collect()
+}
+
+fn get<'a>(columns: &'a [(String, String)], name: &str) -> &'a str {
+    &columns.iter().find(|x| x.0 == name).unwrap().1
+}
+
+#[test]
+fn expands_nested_structs_in_declaration_order() {
+    let columns = columns(Msg::HsHk(HsHk {
+        counter: 120,
+        ..Default::default()
+    }));
+
+    assert_eq!(columns[0], ("instance".to_string(), "Example".to_string()));
+    assert_eq!(columns.last().unwrap().0, "timestamp");
+    assert_eq!(
+        names(&columns)[1..5],
+        [
+            "msg.HsHk.perf.enter_time",
+            "msg.HsHk.perf.elapsed",
+            "msg.HsHk.perf.rate",
+            "msg.HsHk.counter"
+        ]
+    );
+    assert_eq!(get(&columns, "msg.HsHk.counter"), "120");
+}
+
+#[test]
+fn keeps_enum_columns_for_every_variant() {
+    let flush = columns(Msg::DsCmd(DsCmd::Flush));
+    let close = columns(Msg::DsCmd(DsCmd::Close(3)));
+    let add = columns(Msg::DsCmd(DsCmd::AddTlmSet(DsTlmSet {
+        path: "log/a, b".to_string(),
+        ..Default::default()
+    })));
+
+    assert_eq!(
+        names(&flush),
+        ["instance", "msg.DsCmd", "msg.DsCmd.value", "timestamp"]
+    );
+    assert_eq!(names(&close), names(&flush));
+    assert_eq!(names(&add), names(&flush));
+    assert_eq!(get(&flush, "msg.DsCmd"), "Flush");
+    assert_eq!(get(&flush, "msg.DsCmd.value"), "");
+    assert_eq!(get(&close, "msg.DsCmd"), "Close");
+    assert_eq!(get(&close, "msg.DsCmd.value"), "3");
+    // the payload is quoted as it holds commas
+    assert_eq!(get(&add, "msg.DsCmd"), "AddTlmSet");
+    assert!(get(&add, "msg.DsCmd.value").starts_with("\"DsTlmSet {"));
+}
+
+#[test]
+fn keeps_vecs_in_one_column() {
+    let empty = columns(Msg::HsHk(HsHk::default()));
+    let two = columns(Msg::HsHk(HsHk {
+        cpu_usage: vec![10, 20],
+        ..Default::default()
+    }));
+
+    assert_eq!(names(&empty), names(&two));
+    assert_eq!(get(&empty, "msg.HsHk.cpu_usage"), "");
+    assert_eq!(get(&two, "msg.HsHk.cpu_usage"), "10;20");
+}
@@ -0,0 +1,192 @@
+//! ReliableConnectors looped back over serial ports that drop, duplicate and reorder frames
+
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use anyhow::Result;
+use rfe::{
+    connector::Connector,
+    msg::*,
+    reliable::ReliableConnector,
+    serial::{MemSerialPort, SerialConnector, SerialPort},
+};
+
+/// What happens to a frame written to a LossyPort
+#[derive(Debug, Clone, Copy)]
+enum Fault {
+    Drop,
+    Duplicate,
+    /// written after the next frame
+    Delay,
+}
+
+/// Every write is one frame, faults are applied to the writes in order
+struct LossyPort {
+    port: Rc<RefCell<MemSerialPort>>,
+    faults: Rc<RefCell<VecDeque<Fault>>>,
+    delayed: Vec<u8>,
+}
+
+impl SerialPort for LossyPort {
+    fn open(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<usize> {
+        let mut port = self.port.borrow_mut();
+        match self.faults.borrow_mut().pop_front() {
+            Some(Fault::Drop) => {}
+            Some(Fault::Duplicate) => {
+                port.write(bytes)?;
+                port.write(bytes)?;
+            }
+            Some(Fault::Delay) => self.delayed.extend(bytes),
+            None => {
+                port.write(bytes)?;
+                port.write(&core::mem::take(&mut self.delayed))?;
+            }
+        }
+        Ok(bytes.len())
+    }
+
+    fn read(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        self.port.borrow_mut().read(bytes)
+    }
+}
+
+type Reliable = ReliableConnector<SerialConnector<LossyPort>>;
+
+struct End {
+    port: Rc<RefCell<MemSerialPort>>,
+    faults: Rc<RefCell<VecDeque<Fault>>>,
+}
+
+impl End {
+    /// A new ReliableConnector on this end of the link, as if it restarted
+    fn connect(&self) -> Reliable {
+        let port = LossyPort {
+            port: self.port.clone(),
+            faults: self.faults.clone(),
+            delayed: Vec::new(),
+        };
+        ReliableConnector::new(SerialConnector::new(port), [MsgKind::SetTimeCmd])
+    }
+
+    fn add_faults(&self, faults: &[Fault]) {
+        self.faults.borrow_mut().extend(faults);
+    }
+}
+
+fn link() -> (End, End) {
+    let (a, b) = MemSerialPort::new();
+    let end = |port| End {
+        port: Rc::new(RefCell::new(port)),
+        faults: Rc::default(),
+    };
+    (end(a), end(b))
+}
+
+/// The session is taken from the timestamp of the first packet sent
+fn send(connector: &mut Reliable, value: u64, timestamp: u64) {
+    connector.send(&[Rc::new(MsgPacket::new(
+        Instance::Example,
+        Msg::SetTimeCmd(value),
+        timestamp,
+    ))]);
+}
+
+/// Calls recv on both ends, returns the values received by b and the call they were received on
+fn pump(a: &mut Reliable, b: &mut Reliable, calls: u32) -> Vec<(u64, u32)> {
+    let mut received = Vec::new();
+    for call in 1..=calls {
+        assert!(a.recv().is_none());
+        for msg in b.recv().unwrap_or_default() {
+            if let Msg::SetTimeCmd(value) = msg.msg {
+                received.push((value, call));
+            }
+        }
+    }
+    received
+}
+
+fn values(received: &[(u64, u32)]) -> Vec<u64> {
+    received.iter().map(|x| x.0).collect()
+}
+
+#[test]
+fn retransmits_with_backoff() {
+    let (a_end, b_end) = link();
+    let (mut a, mut b) = (a_end.connect(), b_end.connect());
+    // the first send and the first retransmit are lost
+    a_end.add_faults(&[Fault::Drop, Fault::Drop]);
+    send(&mut a, 1, 100);
+
+    let received = pump(&mut a, &mut b, 100);
+    // retransmitted after 10 calls then 20 more
+    assert_eq!(received, vec![(1, 30)]);
+    assert_eq!(a.get_stats().retransmits, 2);
+    assert_eq!(a.get_pending(), 0);
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+    let (a_end, b_end) = link();
+    let (mut a, mut b) = (a_end.connect(), b_end.connect());
+    a_end.add_faults(&[Fault::Drop; 8]);
+    send(&mut a, 1, 100);
+
+    assert!(pump(&mut a, &mut b, 2_000).is_empty());
+    assert_eq!(a.get_stats().retransmits, 7);
+    assert_eq!(a.get_stats().failed, 1);
+    assert_eq!(a.get_pending(), 0);
+}
+
+#[test]
+fn suppresses_duplicates_and_accepts_reordered_packets() {
+    let (a_end, b_end) = link();
+    let (mut a, mut b) = (a_end.connect(), b_end.connect());
+    a_end.add_faults(&[Fault::Duplicate, Fault::Delay]);
+    send(&mut a, 1, 100);
+    send(&mut a, 2, 100);
+    send(&mut a, 3, 100);
+
+    let received = pump(&mut a, &mut b, 100);
+    // 2 arrives after 3 but is still inside the window
+    assert_eq!(values(&received), vec![1, 3, 2]);
+    assert_eq!(b.get_stats().duplicates, 1);
+    assert_eq!(a.get_stats().retransmits, 0);
+    assert_eq!(a.get_pending(), 0);
+}
+
+#[test]
+fn nacks_a_sequence_gap() {
+    let (a_end, b_end) = link();
+    let (mut a, mut b) = (a_end.connect(), b_end.connect());
+    send(&mut a, 1, 100);
+    assert_eq!(values(&pump(&mut a, &mut b, 1)), vec![1]);
+    a_end.add_faults(&[Fault::Drop]);
+    send(&mut a, 2, 100);
+    send(&mut a, 3, 100);
+
+    let received = pump(&mut a, &mut b, 100);
+    // the nack retransmits 2 well before the ack timeout
+    assert_eq!(values(&received), vec![3, 2]);
+    assert!(received[1].1 < 10);
+    assert_eq!(a.get_stats().retransmits, 1);
+    assert_eq!(a.get_pending(), 0);
+}
+
+#[test]
+fn restarts_the_sequence_with_a_new_session() {
+    let (a_end, b_end) = link();
+    let (mut a, mut b) = (a_end.connect(), b_end.connect());
+    send(&mut a, 1, 100);
+    send(&mut a, 2, 100);
+    assert_eq!(values(&pump(&mut a, &mut b, 5)), vec![1, 2]);
+
+    // seq 0 again would be a duplicate in the old session
+    let mut a = a_end.connect();
+    send(&mut a, 3, 200);
+    assert_eq!(values(&pump(&mut a, &mut b, 5)), vec![3]);
+    assert_eq!(b.get_stats().duplicates, 0);
+    assert_eq!(a.get_pending(), 0);
+}
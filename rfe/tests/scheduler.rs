@@ -0,0 +1,164 @@
+//! App rates, phases and tick rate validation on simulated time
+
+use std::{cell::RefCell, rc::Rc};
+
+use anyhow::Result;
+use rfe::{msg::*, time::SimTimeDriver, App, Rate, Rfe, RfeInstance};
+
+/// Records the scheduler tick of every run
+struct TickApp {
+    rate: Rate,
+    ticks: Rc<RefCell<Vec<u64>>>,
+    tick: u64,
+}
+
+impl TickApp {
+    fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            ticks: Rc::default(),
+            tick: 0,
+        }
+    }
+}
+
+impl App for TickApp {
+    fn init(&mut self, _rfe: &mut Rfe) -> Result<()> {
+        Ok(())
+    }
+
+    fn run(&mut self, _rfe: &mut Rfe) {
+        self.ticks.borrow_mut().push(self.tick);
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {
+        // runs every tick, after run
+        self.tick += 1;
+    }
+
+    fn get_app_rate(&self) -> Rate {
+        self.rate
+    }
+
+    fn get_out_data_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+/// Records its name in a log shared with other apps every run
+struct OrderApp {
+    name: &'static str,
+    order: Rc<RefCell<Vec<&'static str>>>,
+}
+
+impl App for OrderApp {
+    fn init(&mut self, _rfe: &mut Rfe) -> Result<()> {
+        Ok(())
+    }
+
+    fn run(&mut self, _rfe: &mut Rfe) {
+        self.order.borrow_mut().push(self.name);
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+#[test]
+fn rejects_invalid_tick_rates() {
+    let time_driver = SimTimeDriver::new();
+    assert!(RfeInstance::new_with_tick_rate(Instance::Example, &time_driver, 0).is_err());
+    assert!(RfeInstance::new_with_tick_rate(Instance::Example, &time_driver, 2_000_000).is_err());
+    assert!(RfeInstance::new_with_tick_rate(Instance::Example, &time_driver, 10).is_ok());
+}
+
+#[test]
+fn rejects_rates_faster_than_the_tick() {
+    let time_driver = SimTimeDriver::new();
+    let mut fast = TickApp::new(Rate::Hz400);
+    let mut slow = TickApp::new(Rate::Hz10);
+    let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+    assert!(instance.add_app("fast", &mut fast).is_err());
+    instance.add_app("slow", &mut slow).unwrap();
+    assert!(instance
+        .set_app_rate("slow", RateKind::App, Rate::Hz200)
+        .is_err());
+    instance
+        .set_app_rate("slow", RateKind::App, Rate::Hz100)
+        .unwrap();
+}
+
+#[test]
+fn rejects_rates_that_arent_whole_ticks() {
+    let time_driver = SimTimeDriver::new();
+    let mut uneven = TickApp::new(Rate::Custom(15_000));
+    let mut even = TickApp::new(Rate::Custom(30_000));
+    let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+    assert!(instance.add_app("uneven", &mut uneven).is_err());
+    instance.add_app("even", &mut even).unwrap();
+    assert!(instance
+        .set_app_rate("even", RateKind::App, Rate::Custom(25_000))
+        .is_err());
+
+    let mut fast = TickApp::new(Rate::Hz200);
+    let mut instance =
+        RfeInstance::new_with_tick_rate(Instance::Example, &time_driver, 300).unwrap();
+    assert!(instance.add_app("fast", &mut fast).is_err());
+}
+
+#[test]
+fn phases_shift_the_tick_apps_run_on() {
+    let time_driver = SimTimeDriver::new();
+    let mut first = TickApp::new(Rate::Hz10);
+    let mut second = TickApp::new(Rate::Hz10);
+    let mut third = TickApp::new(Rate::Hz10);
+    let (first_ticks, second_ticks, third_ticks) = (
+        first.ticks.clone(),
+        second.ticks.clone(),
+        third.ticks.clone(),
+    );
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("first", &mut first).unwrap();
+        instance.add_app("second", &mut second).unwrap();
+        instance.add_app("third", &mut third).unwrap();
+        instance.set_app_phase("third", 0).unwrap();
+        assert!(instance.set_app_phase("missing", 0).is_err());
+        instance.step(20);
+    }
+    // apps are spread by the order they were added unless their phase is set
+    assert_eq!(*first_ticks.borrow(), vec![0, 10]);
+    assert_eq!(*second_ticks.borrow(), vec![9, 19]);
+    assert_eq!(*third_ticks.borrow(), vec![0, 10]);
+}
+
+#[test]
+fn runs_apps_by_priority_then_order_added() {
+    let time_driver = SimTimeDriver::new();
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let mut apps = ["low", "first", "high", "second"].map(|name| OrderApp {
+        name,
+        order: order.clone(),
+    });
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        let [low, first, high, second] = &mut apps;
+        instance.add_app_with_priority("low", low, 200).unwrap();
+        instance.add_app("first", first).unwrap();
+        instance.add_app_with_priority("high", high, 10).unwrap();
+        instance.add_app("second", second).unwrap();
+        instance.step(2);
+    }
+    // lower values run first, equal priorities keep the order they were added
+    assert_eq!(
+        *order.borrow(),
+        ["high", "first", "second", "low"].repeat(2)
+    );
+}
@@ -0,0 +1,131 @@
+//! SerialConnectors over in memory serial ports
+
+use std::{cell::RefCell, rc::Rc};
+
+use anyhow::{anyhow, Result};
+use rfe::{
+    connector::Connector,
+    framing::{encode_msgs, Encoding},
+    msg::*,
+    serial::{MemSerialPort, SerialConnector, SerialPort},
+};
+
+fn counter(counter: u32) -> MsgPacket {
+    MsgPacket::new(
+        Instance::Example,
+        Msg::ExampleOutData(ExampleOutData { counter }),
+        counter as u64,
+    )
+}
+
+fn encode(msgs: &[MsgPacket]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode_msgs(msgs, &mut Encoding::Bincode, &mut buf).unwrap();
+    buf
+}
+
+fn send(connector: &mut impl Connector, msgs: &[MsgPacket]) {
+    connector.send(&msgs.iter().cloned().map(Rc::new).collect::<Vec<_>>());
+}
+
+#[test]
+fn round_trips_msgs() {
+    let (a, b) = MemSerialPort::new();
+    let (mut a, mut b) = (SerialConnector::new(a), SerialConnector::new(b));
+    let event = MsgPacket::new(
+        Instance::Example2,
+        Msg::Event(Event {
+            text: "hello".into(),
+            ..Default::default()
+        }),
+        9,
+    );
+    send(&mut a, &[counter(1), counter(2)]);
+    send(&mut a, std::slice::from_ref(&event));
+    send(&mut b, &[counter(3)]);
+
+    assert_eq!(b.recv().unwrap(), vec![counter(1), counter(2), event]);
+    assert_eq!(a.recv().unwrap(), vec![counter(3)]);
+    assert!(a.recv().is_none());
+    assert!(b.recv().is_none());
+}
+
+#[test]
+fn resyncs_after_garbage_and_a_bad_crc() {
+    let (mut raw, port) = MemSerialPort::new();
+    let mut connector = SerialConnector::new(port);
+    let garbage = [0x12, 0xeb, 0x00, 0x90, 0xeb];
+    let mut corrupted = encode(&[counter(2)]);
+    let last = corrupted.len() - 3;
+    corrupted[last] ^= 0xff;
+
+    raw.write(&garbage).unwrap();
+    raw.write(&encode(&[counter(1)])).unwrap();
+    raw.write(&corrupted).unwrap();
+    raw.write(&encode(&[counter(3)])).unwrap();
+
+    assert_eq!(connector.recv().unwrap(), vec![counter(1), counter(3)]);
+    assert_eq!(connector.get_decoder().get_crc_errors(), 1);
+    assert!(connector.get_decoder().get_dropped_bytes() >= garbage.len() as u32);
+    assert_eq!(connector.get_decoder().get_pending_bytes(), 0);
+}
+
+#[derive(Default)]
+struct PortState {
+    opens: u32,
+    fail_reads: bool,
+}
+
+/// Reads fail while fail_reads is set
+struct FlakyPort {
+    port: MemSerialPort,
+    state: Rc<RefCell<PortState>>,
+}
+
+impl SerialPort for FlakyPort {
+    fn open(&mut self) -> Result<()> {
+        self.state.borrow_mut().opens += 1;
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<usize> {
+        self.port.write(bytes)
+    }
+
+    fn read(&mut self, bytes: &mut [u8]) -> Result<usize> {
+        if self.state.borrow().fail_reads {
+            return Err(anyhow!("device disconnected"));
+        }
+        self.port.read(bytes)
+    }
+}
+
+#[test]
+fn reopens_after_a_port_error() {
+    let (mut raw, port) = MemSerialPort::new();
+    let state = Rc::new(RefCell::new(PortState::default()));
+    let mut connector = SerialConnector::new(FlakyPort {
+        port,
+        state: state.clone(),
+    });
+    assert!(connector.recv().is_none());
+    assert_eq!(state.borrow().opens, 1);
+
+    state.borrow_mut().fail_reads = true;
+    assert!(connector.recv().is_none());
+    state.borrow_mut().fail_reads = false;
+    raw.write(&encode(&[counter(1)])).unwrap();
+    // the port isn't reopened right away
+    assert!(connector.recv().is_none());
+    assert_eq!(state.borrow().opens, 1);
+
+    let mut received = None;
+    for _ in 0..1000 {
+        received = connector.recv();
+        if received.is_some() {
+            break;
+        }
+    }
+    assert_eq!(received, Some(vec![counter(1)]));
+    assert_eq!(state.borrow().opens, 2);
+}
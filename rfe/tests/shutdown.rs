@@ -0,0 +1,89 @@
+//! Shutting an instance down and the msgs sent on the way out
+
+mod common;
+
+use common::*;
+use rfe::{msg::*, time::SimTimeDriver, RfeInstance};
+
+#[test]
+fn shutdown_drains_the_final_msgs_to_the_connectors() {
+    let time_driver = SimTimeDriver::new();
+    let mut counter = CounterApp::default();
+    let mut last = Recorder::new(&[]);
+    last.shutdown_msgs = vec![Msg::ExampleHk(ExampleHk {
+        counter: 7,
+        ..Default::default()
+    })];
+    let mut stopped = Recorder::new(&[]);
+    let mut connector = RecordingConnector::new(&[
+        MsgKind::ExampleOutData,
+        MsgKind::ExampleHk,
+        MsgKind::CmdStatus,
+    ]);
+    let (last_runs, last_shutdowns, to_send) = (
+        last.runs.clone(),
+        last.shutdowns.clone(),
+        last.to_send.clone(),
+    );
+    let (stopped_runs, stopped_shutdowns) = (stopped.runs.clone(), stopped.shutdowns.clone());
+    let (sent, flushes) = (connector.sent.clone(), connector.flushes.clone());
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("counter", &mut counter).unwrap();
+        instance.add_app("last", &mut last).unwrap();
+        instance.add_app("stopped", &mut stopped).unwrap();
+        instance.add_connector(&mut connector);
+        instance
+            .stop_app("stopped", StoppedQueuePolicy::Discard)
+            .unwrap();
+        // long enough for the connector's subscriptions to be requested
+        instance.step(19);
+        to_send.borrow_mut().push(Msg::StartApp(StartAppCmd {
+            app_name: "stopped".into(),
+        }));
+        instance.step(1);
+        instance.shutdown();
+    }
+    let sent = sent.borrow();
+    // the counter of the final run, the status of the last cmd and the hk sent on shutdown
+    assert_eq!(counters(&sent).last(), Some(&20));
+    assert_eq!(
+        cmd_statuses(&sent),
+        vec![(MsgKind::StartApp, CmdState::Executed, CmdError::None)]
+    );
+    assert!(matches!(
+        sent.last().unwrap().msg,
+        Msg::ExampleHk(ExampleHk { counter: 7, .. })
+    ));
+    assert_eq!(flushes.get(), 1);
+    assert_eq!((last_runs.get(), last_shutdowns.get()), (21, 1));
+    // started by the last cmd, so it runs once on the way out
+    assert_eq!((stopped_runs.get(), stopped_shutdowns.get()), (1, 1));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn start_runs_until_the_stop_handle_is_triggered() {
+    use std::{thread, time::Duration};
+
+    let time_driver = SimTimeDriver::new();
+    let mut app = Recorder::new(&[]);
+    let mut connector = RecordingConnector::new(&[]);
+    let (runs, shutdowns) = (app.runs.clone(), app.shutdowns.clone());
+    let flushes = connector.flushes.clone();
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("app", &mut app).unwrap();
+        instance.add_connector(&mut connector);
+        let stop = instance.get_stop_handle();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            stop.stop();
+        });
+        instance.start();
+        stopper.join().unwrap();
+    }
+    assert!(runs.get() > 1);
+    assert_eq!(shutdowns.get(), 1);
+    assert_eq!(flushes.get(), 1);
+}
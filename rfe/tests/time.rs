@@ -0,0 +1,79 @@
+//! MET and system time seen by apps on simulated time
+
+use std::{cell::RefCell, rc::Rc};
+
+use anyhow::Result;
+use rfe::{msg::*, time::SimTimeDriver, App, Rate, Rfe, RfeInstance};
+
+const EPOCH: u64 = 1_700_000_000_000_000;
+/// of the default 100 Hz tick rate
+const TICK_PERIOD: u64 = 10_000;
+
+/// Records the MET and system time of every run
+#[derive(Default)]
+struct ClockApp {
+    times: Rc<RefCell<Vec<(u64, u64)>>>,
+}
+
+impl App for ClockApp {
+    fn init(&mut self, _rfe: &mut Rfe) -> Result<()> {
+        Ok(())
+    }
+
+    fn run(&mut self, rfe: &mut Rfe) {
+        self.times
+            .borrow_mut()
+            .push((rfe.get_met_time(), rfe.get_system_time()));
+    }
+
+    fn hk(&mut self, _rfe: &mut Rfe) {}
+
+    fn out_data(&mut self, _rfe: &mut Rfe) {}
+
+    fn get_app_rate(&self) -> Rate {
+        Rate::Hz100
+    }
+}
+
+#[test]
+fn time_advances_one_tick_per_run() {
+    let time_driver = SimTimeDriver::new_with_epoch(EPOCH);
+    let mut app = ClockApp::default();
+    let times = app.times.clone();
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("clock", &mut app).unwrap();
+        instance.run();
+        instance.step(2);
+        instance.run();
+    }
+    let expected = (0..4)
+        .map(|x| (x * TICK_PERIOD, EPOCH + x * TICK_PERIOD))
+        .collect::<Vec<_>>();
+    assert_eq!(*times.borrow(), expected);
+}
+
+#[test]
+fn advance_moves_time_between_ticks() {
+    let time_driver = SimTimeDriver::new_with_epoch(EPOCH);
+    let mut app = ClockApp::default();
+    let times = app.times.clone();
+    {
+        let mut instance = RfeInstance::new(Instance::Example, &time_driver);
+        instance.add_app("clock", &mut app).unwrap();
+        instance.step(1);
+        time_driver.advance(2_500);
+        instance.step(1);
+        time_driver.advance(500);
+        instance.run();
+    }
+    assert_eq!(time_driver.get_advanced(), 3_000);
+    assert_eq!(
+        *times.borrow(),
+        vec![
+            (0, EPOCH),
+            (TICK_PERIOD + 2_500, EPOCH + TICK_PERIOD + 2_500),
+            (2 * TICK_PERIOD + 3_000, EPOCH + 2 * TICK_PERIOD + 3_000),
+        ]
+    );
+}
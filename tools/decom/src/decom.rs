@@ -1,9 +1,8 @@
-#![feature(bufreader_peek)]
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
     env::args,
-    fs::{read_dir, OpenOptions},
-    io::{BufReader, Write},
+    fs::{read_dir, File, OpenOptions},
+    io::{BufRead, BufReader, Seek, Write},
     path::PathBuf,
     str::FromStr,
     thread::spawn,
@@ -11,58 +10,117 @@ use std::{
 
 use anyhow::Result;
 use bincode::decode_from_std_read;
+use ds::{has_ds_magic, DsFileDecoder};
 use log::*;
 use rfe::{msg::MsgPacket, BINCODE_CONFIG};
 use simple_logger::SimpleLogger;
 
+/// Files written before records were framed, decoding stops at the first bad record
+fn read_unframed(file_path: &str, on_msg: &mut impl FnMut(MsgPacket) -> Result<()>) -> Result<()> {
+    warn!("{file_path} has no header, reading unframed records");
+    let mut r = BufReader::new(File::open(file_path)?);
+    while !r.fill_buf()?.is_empty() {
+        let offset = r.stream_position()?;
+        match decode_from_std_read::<MsgPacket, _, _>(&mut r, BINCODE_CONFIG) {
+            Ok(msg) => on_msg(msg)?,
+            Err(e) => {
+                error!(
+                    "{file_path}: failed to decode record at byte {offset} {e}, {} bytes lost",
+                    r.get_ref().metadata()?.len() - offset
+                );
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the file a buffer at a time, passing each msg to on_msg
+fn read_msgs(file_path: &str, mut on_msg: impl FnMut(MsgPacket) -> Result<()>) -> Result<()> {
+    let mut r = BufReader::new(File::open(file_path)?);
+    let mut decoder = DsFileDecoder::new();
+    let mut start = Vec::new();
+    let mut read_any = false;
+    loop {
+        let buf = r.fill_buf()?;
+        if buf.is_empty() {
+            decoder.finish();
+        }
+        if start.is_empty() {
+            start = buf.to_vec();
+        }
+        decoder.push(buf);
+        let len = buf.len();
+        r.consume(len);
+        while let Some(msg) = decoder.next_packet()? {
+            read_any = true;
+            on_msg(msg)?;
+        }
+        if len == 0 {
+            break;
+        }
+    }
+
+    let contents = decoder.into_contents();
+    // a ds file with damaged first bytes still has its magic or some records to resync on
+    if contents.header.is_none() && !read_any && !has_ds_magic(&start) {
+        return read_unframed(file_path, &mut on_msg);
+    }
+
+    if let Some(header) = &contents.header {
+        info!(
+            "{file_path}: instance {:?}, started {} us, schema {:#x}",
+            header.instance, header.start_time, header.schema_hash
+        );
+    }
+    for lost in &contents.lost_records {
+        warn!("{file_path}: lost records {}..{}", lost.start, lost.end);
+    }
+    if contents.crc_errors > 0 || contents.dropped_bytes > 0 || contents.undecodable_records > 0 {
+        warn!(
+            "{file_path}: {} crc errors, {} bytes dropped, {} records failed to decode",
+            contents.crc_errors, contents.dropped_bytes, contents.undecodable_records
+        );
+    }
+    Ok(())
+}
+
 fn decom_file(file_path: String, out_dir: String) -> Result<()> {
     info!("decomming {file_path}");
-    let f = OpenOptions::new().read(true).open(file_path)?;
-    let mut rea = BufReader::new(f);
     let mut files = HashMap::new();
 
-    'outer: loop {
-        while let Ok(_) = rea.peek(1) {
-            let msg = match decode_from_std_read::<MsgPacket, _, _>(&mut rea, BINCODE_CONFIG) {
-                Ok(m) => m,
-                Err(_) => break 'outer,
-            };
-            if !files.contains_key(&msg.msg.kind()) {
+    read_msgs(&file_path, |mut msg| {
+        let columns = msg.to_csv();
+        let w = match files.entry(msg.msg.kind()) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
                 let path = PathBuf::from_str(&out_dir)?
                     .join(format!("{:?}.csv", msg.msg.kind()).to_lowercase());
                 let exists = path.exists();
-                let mut w = OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .append(true)
-                    .open(path)?;
+                let mut w = OpenOptions::new().create(true).append(true).open(path)?;
 
                 if !exists {
-                    let values = msg.to_csv_clean();
-                    let mut line = values
+                    let mut line = columns
                         .iter()
-                        .map(|x| x.split_once(" = ").expect("unexpected csv data").0)
+                        .map(|x| x.0.as_str())
                         .collect::<Vec<&str>>()
                         .join(",");
                     line += "\n";
-                    w.write(line.as_bytes())?;
+                    w.write_all(line.as_bytes())?;
                 }
-
-                files.insert(msg.msg.kind(), w);
+                e.insert(w)
             }
+        };
 
-            if let Some(w) = files.get_mut(&msg.msg.kind()) {
-                let values = msg.to_csv_clean();
-                let mut line = values
-                    .iter()
-                    .map(|x| x.split_once(" = ").expect("unexpected csv data").1)
-                    .collect::<Vec<&str>>()
-                    .join(",");
-                line += "\n";
-                w.write(line.as_bytes())?;
-            }
-        }
-    }
+        let mut line = columns
+            .iter()
+            .map(|x| x.1.as_str())
+            .collect::<Vec<&str>>()
+            .join(",");
+        line += "\n";
+        w.write_all(line.as_bytes())?;
+        Ok(())
+    })?;
     return Ok(());
 }
 